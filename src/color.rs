@@ -0,0 +1,29 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use ansi_term::Style;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Enables or disables colored output for the whole program
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether colored output is enabled
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Whether the environment variable `NO_COLOR` asks for output without colors
+pub fn disabled_by_env() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+}
+
+/// Returns `style` if colored output is enabled, otherwise a plain style
+pub fn color(style: impl Into<Style>) -> Style {
+    if enabled() {
+        style.into()
+    } else {
+        Style::new()
+    }
+}
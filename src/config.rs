@@ -0,0 +1,226 @@
+use std::path::PathBuf;
+
+use dirs::config_dir;
+
+use crate::error::DiaryError;
+
+/// How the content typed in `add` gets processed before it is stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ContentMode {
+    /// Collapses all whitespace into single spaces
+    Squash,
+    /// Stores the content exactly as typed
+    #[default]
+    Raw,
+    /// Collapses whitespace within paragraphs but keeps the blank lines between them
+    Paragraphs,
+}
+
+impl ContentMode {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "squash" => Some(ContentMode::Squash),
+            "raw" => Some(ContentMode::Raw),
+            "paragraphs" => Some(ContentMode::Paragraphs),
+            _ => None
+        }
+    }
+
+    /// Processes the `raw` content according to the mode, the trailing
+    /// blank line which terminates the input is always removed.
+    pub fn apply(&self, raw: &str) -> String {
+        match self {
+            ContentMode::Squash => raw.split_whitespace().collect::<Vec<&str>>().join(" "),
+            ContentMode::Raw => raw.trim_end_matches(['\r', '\n']).to_string(),
+            ContentMode::Paragraphs => {
+                let mut paragraphs = Vec::new();
+                let mut current: Vec<&str> = Vec::new();
+
+                for line in raw.lines() {
+                    if line.trim().is_empty() {
+                        if !current.is_empty() {
+                            paragraphs.push(current.join(" "));
+                            current.clear();
+                        }
+                    } else {
+                        current.extend(line.split_whitespace());
+                    }
+                }
+
+                if !current.is_empty() {
+                    paragraphs.push(current.join(" "));
+                }
+
+                paragraphs.join("\n\n")
+            }
+        }
+    }
+}
+
+/// Format `export` writes the entries in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    /// Human readable plain text
+    #[default]
+    Txt,
+    /// An array with an object per entry
+    Json,
+    /// A section per entry for publishing
+    Markdown,
+    /// A row per entry for spreadsheets
+    Csv,
+}
+
+impl ExportFormat {
+    pub const NAMES: [&'static str; 4] = ["txt", "json", "markdown", "csv"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "txt" => Some(ExportFormat::Txt),
+            "json" => Some(ExportFormat::Json),
+            "markdown" => Some(ExportFormat::Markdown),
+            "csv" => Some(ExportFormat::Csv),
+            _ => None
+        }
+    }
+}
+
+/// Display flags which are set without giving them, the flags themselves can only turn them on
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DisplayDefaults {
+    pub nocontent: bool,
+    pub id: bool,
+    pub hash: bool,
+    pub keywords: bool,
+    pub nodate: bool,
+    pub hidden: bool,
+}
+
+/// Settings read from the config file, every setting is optional
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub content_mode: ContentMode,
+    /// Format `export` uses when it isn't given `--format`
+    pub export_format: ExportFormat,
+    /// How many entries `list` and `search` print at most, `None` means unlimited
+    pub max_results: Option<usize>,
+    /// How many characters the content of an entry may have at most, `None` means unlimited
+    pub max_content_length: Option<usize>,
+    pub display: DisplayDefaults,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            content_mode: ContentMode::default(),
+            export_format: ExportFormat::default(),
+            max_results: Some(500),
+            max_content_length: None,
+            display: DisplayDefaults::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Location of the config file, uses the environment variable `DIDI_CONFIG` if
+    /// specified otherwise `didi/config.toml` in the users config directory.
+    fn path() -> Option<PathBuf> {
+        if let Ok(a) = std::env::var("DIDI_CONFIG") {
+            return Some(PathBuf::from(a));
+        }
+
+        config_dir().map(|mut d| {
+            d.push("didi");
+            d.push("config.toml");
+            d
+        })
+    }
+
+    /// Loads the config file, if there is none the default config is used.
+    pub fn load() -> Result<Self, DiaryError> {
+        let path = match Self::path() {
+            Some(p) if p.exists() => p,
+            _ => return Ok(Config::default())
+        };
+
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| DiaryError::Invalid(format!("couldn't read config file '{}': {}", path.display(), e)))?;
+
+        Self::parse(&text).map_err(|e| DiaryError::Invalid(format!("invalid config file '{}': {}", path.display(), e)))
+    }
+
+    /// Parses simple `key = value` lines, empty lines and comments starting with `#`
+    /// are ignored. String values have to be quoted.
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => return Err(format!("line {}: expected `key = value`", n + 1))
+            };
+
+            match key {
+                "content_mode" => {
+                    let name = parse_string(value)
+                        .ok_or_else(|| format!("line {}: `content_mode` has to be a string", n + 1))?;
+                    config.content_mode = ContentMode::from_name(&name)
+                        .ok_or_else(|| format!("line {}: unknown content mode '{}'", n + 1, name))?;
+                }
+                "export_format" => {
+                    let name = parse_string(value)
+                        .ok_or_else(|| format!("line {}: `export_format` has to be a string", n + 1))?;
+                    config.export_format = ExportFormat::from_name(&name)
+                        .ok_or_else(|| format!("line {}: unknown export format '{}'", n + 1, name))?;
+                }
+                "max_results" => {
+                    let max = value.parse::<usize>()
+                        .map_err(|_| format!("line {}: `max_results` has to be a positive number", n + 1))?;
+                    config.max_results = if max == 0 { None } else { Some(max) };
+                }
+                "max_content_length" => {
+                    let max = value.parse::<usize>()
+                        .map_err(|_| format!("line {}: `max_content_length` has to be a positive number", n + 1))?;
+                    config.max_content_length = if max == 0 { None } else { Some(max) };
+                }
+                "nocontent" | "id" | "hash" | "keywords" | "nodate" | "hidden" => {
+                    let on = parse_bool(value)
+                        .ok_or_else(|| format!("line {}: `{}` has to be true or false", n + 1, key))?;
+                    let flag = match key {
+                        "nocontent" => &mut config.display.nocontent,
+                        "id" => &mut config.display.id,
+                        "hash" => &mut config.display.hash,
+                        "keywords" => &mut config.display.keywords,
+                        "nodate" => &mut config.display.nodate,
+                        _ => &mut config.display.hidden,
+                    };
+                    *flag = on;
+                }
+                _ => return Err(format!("line {}: unknown setting '{}'", n + 1, key))
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Parses a `true` or `false` value
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None
+    }
+}
+
+/// Parses a quoted string value
+fn parse_string(value: &str) -> Option<String> {
+    value.strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .map(|v| v.to_string())
+}
@@ -1,13 +1,28 @@
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs::{create_dir_all, File};
+use std::io::{stdout, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 use ansi_term::Color::*;
-use chrono::{DateTime, Local};
+use ansi_term::Style;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
 use dirs::home_dir;
-use rusqlite::{Connection, OpenFlags};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, ToSql};
 use rusqlite::params;
-use sha2::{Digest, Sha256};
+use unicode_width::UnicodeWidthStr;
 use whoami::username;
 
+use crate::color::color;
+use crate::error::DiaryError;
+use crate::hash::HashAlgorithm;
+use crate::json::{self, Json};
+use crate::language::{fuzzy_score, normalize, salient_words};
+use crate::progress::Progress;
+use crate::regex::Regex;
+
 #[derive(Clone, Debug)]
 pub struct Entry {
     id: i64,
@@ -17,268 +32,3050 @@ pub struct Entry {
     title: String,
     content: String,
     hidden: bool,
+    /// Pinned entries are listed before all others
+    pinned: bool,
+    /// ISO 639-1 code of the language the content is written in
+    language: Option<String>,
+    /// Custom `key=value` pairs stored alongside the entry
+    metadata: Vec<(String, String)>,
+    /// Paths of files linked to the entry
+    attachments: Vec<String>,
+    /// Mood from 1 (bad) to 5 (great)
+    mood: Option<u8>,
+    /// Name of the database the entry is from, only set when multiple databases are open
+    source: Option<String>,
 }
 
-pub struct Diary {
-    connection: Connection,
+impl Entry {
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Number of words in the content
+    pub fn word_count(&self) -> usize {
+        self.content.split_whitespace().count()
+    }
+
+    /// Id shown to the user, prefixed with the database name when multiple databases are open
+    fn display_id(&self) -> String {
+        match &self.source {
+            Some(s) => format!("{}:{}", s, self.id),
+            None => format!("{}", self.id)
+        }
+    }
+
+    /// Counts how often the lowercase `searchfor` terms appear in the title and keywords
+    fn count_matches(&self, searchfor: &[String]) -> usize {
+        let title = normalize(&self.title);
+        let keywords: Vec<String> = self.keywords.iter().map(|k| normalize(k)).collect();
+
+        searchfor.iter()
+            .map(|s| normalize(s))
+            .map(|s| {
+                title.matches(s.as_str()).count()
+                    + keywords.iter().map(|k| k.matches(s.as_str()).count()).sum::<usize>()
+            })
+            .sum()
+    }
 }
 
-impl Diary {
-    fn get_database_url(expect_existence: bool) -> PathBuf {
-        if let Ok(a) = std::env::var("DIDI_URL") {
-            let p = PathBuf::from(a);
+/// Field entries can be sorted by when listing them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortField {
+    Id,
+    Date,
+    Title,
+}
 
-            if !p.exists() && expect_existence {
-                panic!("Error: database specified in DIDI_URL doesn't exist maybe use `didi create`")
-            }
+impl SortField {
+    pub const NAMES: [&'static str; 3] = ["date", "id", "title"];
 
-            p
-        } else {
-            match home_dir() {
-                Some(mut d) => {
-                    d.push("digital_diary.sqlite");
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "id" => Some(SortField::Id),
+            "date" => Some(SortField::Date),
+            "title" => Some(SortField::Title),
+            _ => None
+        }
+    }
 
-                    if !d.as_path().exists() && expect_existence {
-                        panic!("Error: no database file found. Specify DIDI_URL or use `didi create`")
-                    }
+    /// Terms of an `ORDER BY` clause sorting by the field, ties are ordered by id
+    fn order_terms(&self, reverse: bool) -> String {
+        let direction = if reverse { "DESC" } else { "ASC" };
+        match self {
+            SortField::Id => format!("id {}", direction),
+            SortField::Date => format!("date {0}, id {0}", direction),
+            SortField::Title => format!("title COLLATE normalized {0}, id {0}", direction),
+        }
+    }
+}
+
+/// Presets of the display flags
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayFormat {
+    /// Everything stored for an entry
+    Full,
+    /// Title and date without the content
+    Compact,
+    /// Id, date and title on a single line
+    Oneline,
+}
+
+impl DisplayFormat {
+    pub const NAMES: [&'static str; 3] = ["full", "compact", "oneline"];
 
-                    d
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "full" => Some(DisplayFormat::Full),
+            "compact" => Some(DisplayFormat::Compact),
+            "oneline" => Some(DisplayFormat::Oneline),
+            _ => None
+        }
+    }
+}
+
+/// What `list --group-by` groups entries by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupBy {
+    Day,
+    Month,
+    Keyword,
+}
+
+impl GroupBy {
+    pub const NAMES: [&'static str; 3] = ["day", "month", "keyword"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "day" => Some(GroupBy::Day),
+            "month" => Some(GroupBy::Month),
+            "keyword" => Some(GroupBy::Keyword),
+            _ => None
+        }
+    }
+}
+
+/// Partitions `entries` into groups named by the day, month or keyword, keeping the order of the
+/// entries within a group. Day and month groups are ordered by their first entry, keyword groups
+/// alphabetically with entries without keywords last. An entry is part of every keyword group it
+/// has a keyword of.
+fn group_entries(entries: Vec<Entry>, group_by: GroupBy, options: &DisplayOptions) -> Vec<(Option<String>, Vec<Entry>)> {
+    let mut groups: Vec<(Option<String>, Vec<Entry>)> = Vec::new();
+    let mut add = |key: String, e: Entry| match groups.iter_mut().find(|(k, _)| k.as_deref() == Some(key.as_str())) {
+        Some((_, g)) => g.push(e),
+        None => groups.push((Some(key), vec![e]))
+    };
+
+    for e in entries {
+        let date = options.timezone.map_or(e.date.naive_local(), |tz| tz.naive_local(&e.date));
+        match group_by {
+            GroupBy::Day => add(date.format("%Y-%m-%d").to_string(), e),
+            GroupBy::Month => add(date.format("%Y-%m").to_string(), e),
+            GroupBy::Keyword => {
+                let mut keywords: Vec<String> = e.keywords.iter().filter(|k| !k.trim().is_empty()).cloned().collect();
+                keywords.dedup();
+                if keywords.is_empty() {
+                    add(String::from("(no keywords)"), e);
+                } else {
+                    for k in keywords {
+                        add(k, e.clone());
+                    }
                 }
-                None => panic!("Error: couldn't retrieve home directory")
             }
         }
     }
 
-    pub fn open() -> Self {
-        let url = Self::get_database_url(true);
-        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_URI;
-        let connection = match Connection::open_with_flags(&url, flags) {
-            Ok(c) => c,
-            Err(e) => panic!("Error: couldn't open database connection: {:?}", e)
-        };
+    if group_by == GroupBy::Keyword {
+        groups.sort_by_key(|(k, _)| (k.as_deref() == Some("(no keywords)"), k.clone()));
+    }
+    groups
+}
 
-        println!("Welcome {} at '{}'!\n", Cyan.paint(username()),
-                 Cyan.paint(url.as_path().to_str().unwrap()));
+/// Sorts `entries` by `sort`, without a field they are sorted by date with the newest first
+fn sort_entries(entries: &mut [Entry], sort: Option<SortField>, reverse: bool) {
+    match sort {
+        Some(SortField::Id) => entries.sort_by(|a, b| a.id.cmp(&b.id).then_with(|| a.source.cmp(&b.source))),
+        Some(SortField::Title) => entries.sort_by_cached_key(|e| (normalize(&e.title), e.id)),
+        Some(SortField::Date) => entries.sort_by_key(|e| (e.date, e.id)),
+        None => entries.sort_by_key(|e| std::cmp::Reverse((e.date, e.id))),
+    }
 
-        Diary { connection }
+    if reverse {
+        entries.reverse();
     }
+}
 
-    pub fn create() -> PathBuf {
-        let url = Self::get_database_url(false);
-        let connection = match Connection::open_with_flags(&url, OpenFlags::default()) {
-            Ok(c) => c,
-            Err(e) => panic!("Error: couldn't open database connection: {:?}", e)
-        };
+/// `ORDER BY` clause sorting the entries in the database like `sort_entries`, with `pinned_first`
+/// the pinned entries come first
+fn order_by(sort: Option<SortField>, reverse: bool, pinned_first: bool) -> String {
+    let terms = match sort {
+        Some(s) => s.order_terms(reverse),
+        None => SortField::Date.order_terms(!reverse),
+    };
+    if pinned_first {
+        format!("ORDER BY pinned DESC, {}", terms)
+    } else {
+        format!("ORDER BY {}", terms)
+    }
+}
 
-        if let Err(e) = connection.execute(
-            r#"create table entries
-                (
-                    id       INTEGER not null,
-                    hash     BLOB    not null,
-                    date     TEXT    not null,
-                    keywords TEXT    not null,
-                    title    TEXT    not null,
-                    content  TEXT    not null,
-                    hidden   INTEGER not null,
-                    primary key (id autoincrement),
-                    unique (id)
-                );"#, []) {
-            panic!("Error: couldn't create database tables: {:?}", e)
+/// Controls which entries and what of them gets printed. If a field is `true` it
+/// will get printed. Hidden entries will get printed if `hidden` is `true`.
+#[derive(Clone, Debug, Default)]
+pub struct DisplayOptions {
+    pub date: bool,
+    pub id: bool,
+    pub hash: bool,
+    pub keywords: bool,
+    pub content: bool,
+    pub hidden: bool,
+    /// Only hidden entries get printed
+    pub only_hidden: bool,
+    /// Only pinned entries get printed
+    pub pinned_only: bool,
+    pub match_count: bool,
+    pub language: bool,
+    pub word_count: bool,
+    pub metadata: bool,
+    pub attachments: bool,
+    pub mood: bool,
+    /// Shows how long ago the entry was written, e.g. `(3 days ago)`
+    pub relative: bool,
+    /// Prints the entries in groups with a header before each one
+    pub group_by: Option<GroupBy>,
+    /// Collapses runs of three or more blank lines in the content into a single one
+    pub compact: bool,
+    /// Shortens the content to this many characters
+    pub preview: Option<usize>,
+    /// Only entries having all of these metadata pairs get printed
+    pub filter_metadata: Vec<(String, String)>,
+    /// Prints a single line per entry with id, date and title ignoring the other fields
+    pub summary: bool,
+    /// Timezone the dates are shown in, `None` uses the local timezone
+    pub timezone: Option<Timezone>,
+    /// Only entries written in this language get printed
+    pub filter_language: Option<String>,
+    /// Only entries added after this point in time get printed
+    pub since: Option<DateTime<Local>>,
+    /// Only entries written at or after `from` and before `to` get printed
+    pub from: Option<DateTime<Local>>,
+    pub to: Option<DateTime<Local>>,
+    /// Field the entries are sorted by, `None` orders them by id or by date when combining databases
+    pub sort: Option<SortField>,
+    /// Reverses the order of the entries
+    pub reverse: bool,
+    /// Writes the entries as a JSON array, the summary goes to `stderr`
+    pub json: bool,
+    /// How many entries get printed at most
+    pub limit: Option<usize>,
+    /// How many entries are skipped before printing, `Some` shows the range of printed entries in the footer
+    pub offset: Option<usize>,
+}
+
+impl DisplayOptions {
+    /// Replaces the display flags with the ones of the preset `format`, the filters stay the same
+    pub fn with_format(self, format: DisplayFormat) -> Self {
+        let shown = format == DisplayFormat::Full;
+        DisplayOptions {
+            date: true,
+            id: shown,
+            hash: shown,
+            keywords: shown,
+            content: shown,
+            language: shown,
+            word_count: shown,
+            metadata: shown,
+            attachments: shown,
+            mood: shown,
+            summary: format == DisplayFormat::Oneline,
+            ..self
         }
+    }
 
-        url
+    /// Formats `date` in the configured timezone
+    fn format_date(&self, date: &DateTime<Local>) -> String {
+        match self.timezone {
+            Some(tz) => tz.format(date),
+            None => date.to_rfc2822()
+        }
     }
 
-    /// Adds an entry to the database
-    ///
-    /// `keywords` have to be lowercase
-    pub fn add(&mut self, mut keywords: Vec<String>, title: String, content: String) {
-        keywords.sort();
-        keywords.dedup();
-        let keywords_str = keywords.join(";");
-        let now = Local::now().to_rfc3339();
-
-        let hash: Vec<u8> = {
-            let mut hasher = Sha256::new();
-            hasher.update(&keywords_str);
-            hasher.update(&title);
-            hasher.update(&content);
-            hasher.update(&now);
-            hasher.finalize().to_vec()
-        };
+    /// Whether the entry passes the filters and gets printed
+    fn shows(&self, e: &Entry) -> bool {
+        let visible = if self.only_hidden { e.hidden } else { !e.hidden || self.hidden };
+        visible && (e.pinned || !self.pinned_only)
+            && (self.filter_language.is_none() || e.language == self.filter_language)
+            && self.filter_metadata.iter().all(|m| e.metadata.contains(m))
+            && self.since.is_none_or(|s| e.date > s)
+            && self.from.is_none_or(|f| e.date >= f) && self.to.is_none_or(|t| e.date < t)
+    }
 
-        match self.connection.execute(
-            r#"
-            INSERT INTO entries (hash, date, keywords, title, content, hidden) VALUES
-            (?1, ?2, ?3, ?4, ?5, false)
-            "#, params![hash, now, keywords_str, title, content]) {
-            Err(e) => panic!("Error: couldn't insert entry: {:?}", e),
-            Ok(_) => println!("Added {}!", Cyan.paint(title))
+    /// SQL condition checking the same filters as `shows` for the entries with an id greater than
+    /// `after_id`, together with the values bound to its parameters
+    fn condition(&self, after_id: i64) -> (String, Vec<Box<dyn ToSql>>) {
+        let mut conditions = vec![String::from("id > ?")];
+        let mut values: Vec<Box<dyn ToSql>> = vec![Box::new(after_id)];
+
+        if self.only_hidden {
+            conditions.push(String::from("hidden = 1"));
+        } else if !self.hidden {
+            conditions.push(String::from("hidden = 0"));
+        }
+        if self.pinned_only {
+            conditions.push(String::from("pinned = 1"));
+        }
+        if let Some(l) = &self.filter_language {
+            conditions.push(String::from("language = ?"));
+            values.push(Box::new(l.clone()));
+        }
+        for (key, value) in &self.filter_metadata {
+            conditions.push(String::from("EXISTS (SELECT 1 FROM metadata WHERE entry_id = entries.id AND key = ? AND value = ?)"));
+            values.push(Box::new(key.clone()));
+            values.push(Box::new(value.clone()));
+        }
+        // the dates are stored in UTC like the bound values, so comparing them as text orders them in time
+        for (date, operator) in [(self.since, ">"), (self.from, ">="), (self.to, "<")] {
+            if let Some(d) = date {
+                conditions.push(format!("date {} ?", operator));
+                values.push(Box::new(d));
+            }
+        }
+
+        (conditions.join(" AND "), values)
+    }
+}
+
+/// Describes how long before `now` the point in time `date` is, e.g. `3 days ago`. Months
+/// are counted as 30 days and years as 365 days.
+fn relative_time(date: &DateTime<Local>, now: &DateTime<Local>) -> String {
+    let seconds = (*now - *date).num_seconds();
+    if seconds < 0 {
+        return String::from("in the future");
+    }
+    if seconds < 10 {
+        return String::from("just now");
+    }
+
+    let (n, unit) = match seconds {
+        s if s < 60 => (s, "second"),
+        s if s < 3600 => (s / 60, "minute"),
+        s if s < 86400 => (s / 3600, "hour"),
+        s if s < 7 * 86400 => (s / 86400, "day"),
+        s if s < 30 * 86400 => (s / (7 * 86400), "week"),
+        s if s < 365 * 86400 => (s / (30 * 86400), "month"),
+        s => (s / (365 * 86400), "year"),
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
+
+/// Paints `text` in `style` with every occurrence of the `terms` highlighted, occurrences
+/// are found ignoring case and accents like `search` does.
+fn highlight(text: &str, terms: &[String], style: Style) -> String {
+    // the normalized text together with the index of the char every normalized byte comes from
+    let chars: Vec<char> = text.chars().collect();
+    let mut normalized = String::new();
+    let mut origin = Vec::new();
+    for (i, c) in chars.iter().enumerate() {
+        let n = normalize(&c.to_string());
+        origin.extend(std::iter::repeat_n(i, n.len()));
+        normalized.push_str(&n);
+    }
+
+    let mut marked = vec![false; chars.len()];
+    for term in terms.iter().map(|t| normalize(t)).filter(|t| !t.is_empty()) {
+        for (start, _) in normalized.match_indices(term.as_str()) {
+            marked[origin[start]..=origin[start + term.len() - 1]].fill(true);
+        }
+    }
+
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let end = (i..chars.len()).find(|&j| marked[j] != marked[i]).unwrap_or(chars.len());
+        let part: String = chars[i..end].iter().collect();
+        let part_style = if marked[i] { color(Yellow.bold()) } else { style };
+        out.push_str(&part_style.paint(part).to_string());
+        i = end;
+    }
+    out
+}
+
+/// Shortens `text` to its first `n` characters followed by an ellipsis, shorter texts are kept as they are
+fn preview(text: &str, n: usize) -> String {
+    match text.char_indices().nth(n) {
+        Some((end, _)) => format!("{}\u{2026}", &text[..end]),
+        None => text.to_string()
+    }
+}
+
+/// Quotes `field` for CSV if it contains a comma, quote or line break, quotes are doubled
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Collapses every run of three or more blank lines in `text` into a single blank line
+fn compact(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut blank = 0;
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            blank += 1;
+            continue;
+        }
+
+        let keep = if blank >= 3 { 1 } else { blank };
+        lines.extend(std::iter::repeat_n("", keep));
+        blank = 0;
+        lines.push(line);
+    }
+
+    lines.join("\n")
+}
+
+/// Outcome of an import, records are either imported, skipped as duplicates or rejected
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub skipped: usize,
+    pub rejected: usize,
+}
+
+/// Identifies an entry, `source` is the name of its database when several are combined
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EntryId {
+    pub source: Option<String>,
+    pub id: i64,
+}
+
+impl fmt::Display for EntryId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.source {
+            Some(s) => write!(f, "{}:{}", s, self.id),
+            None => write!(f, "{}", self.id)
         }
     }
+}
 
-    /// Retrieves all entries from the database
-    fn get_entries(&mut self) -> Vec<Entry> {
-        let mut stmt = match self.connection.prepare("SELECT * FROM entries") {
-            Ok(o) => o,
-            Err(e) => panic!("Error: can't build sql statement: {:?}", e)
+impl EntryId {
+    /// Parses an id which can be prefixed with the name of its database, e.g. `diary2023:5`
+    pub fn parse(a: &str) -> Result<Self, String> {
+        let (source, id) = match a.rsplit_once(':') {
+            Some((s, i)) if !s.is_empty() => (Some(s.to_string()), i),
+            Some(_) => return Err(String::from("the database name in front of ':' is missing")),
+            None => (None, a)
         };
 
-        stmt.query_map(params![], |row| {
-            let id = row.get(0)?;
-            let hash = row.get(1)?;
-            let date = row.get(2)?;
-            let keywords: Vec<String> = {
-                let k: String = row.get(3)?;
-                k.split(';').map(|s| s.to_string()).collect()
-            };
-            let title = row.get(4)?;
-            let content = row.get(5)?;
-            let hidden = row.get(6)?;
+        match id.parse::<u64>() {
+            Ok(id) => Ok(EntryId { source, id: id as i64 }),
+            Err(_) => Err(String::from("argument only accepts positive numbers (u64), optionally \
+                                        prefixed with a database name like `diary2023:5`"))
+        }
+    }
+}
 
-            Ok(Entry {
-                id,
-                hash,
-                date,
-                keywords,
-                title,
-                content,
-                hidden,
-            })
-        }).unwrap().map(|r| r.unwrap()).collect()
+/// An entry whose stored hash doesn't match the one computed from its fields
+struct HashMismatch {
+    id: i64,
+    title: String,
+    stored: Vec<u8>,
+    computed: Vec<u8>,
+    /// The fields the hash is computed from
+    inputs: [(&'static str, String); 4],
+}
+
+/// Subdirectories an export into a directory gets split into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitBy {
+    Year,
+    Month,
+}
+
+impl SplitBy {
+    /// Directory inside `dir` an entry from `date` gets exported to
+    fn directory(split_by: Option<SplitBy>, dir: &Path, date: &DateTime<Local>) -> PathBuf {
+        let mut path = dir.to_path_buf();
+
+        match split_by {
+            Some(SplitBy::Year) => path.push(format!("{:04}", date.year())),
+            Some(SplitBy::Month) => {
+                path.push(format!("{:04}", date.year()));
+                path.push(format!("{:02}", date.month()));
+            }
+            None => {}
+        }
+
+        path
     }
+}
 
-    /// Prints the given entries, which and what gets printed can be customised using the
-    /// parameters. If a parameter is `true` it will get printed. Hidden entries
-    /// will get printed if `hidden` is `true`.
-    fn print_entries(entries: Vec<Entry>, date: bool, id: bool, hash: bool, keywords: bool,
-                     content: bool, hidden: bool) {
-        let mut counter = 0;
-        let mut iter = entries.into_iter().filter(|a| !a.hidden || hidden);
+/// Turns a title into a lowercase file name consisting of letters, digits and dashes
+fn slugify(title: &str) -> String {
+    let slug: String = title.to_lowercase().chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+    let slug = slug.split_whitespace().collect::<Vec<&str>>().join("-");
+    slug.chars().take(40).collect()
+}
 
-        loop {
-            match iter.next() {
-                Some(e) => {
-                    println!("{:-<1$}\n", "", termsize::get().unwrap().cols as usize);
-                    counter += 1;
+/// Parses a date given as `YYYY-MM-DD` into the start of that day in local time, if
+/// `end_of_day` is `true` the start of the following day is returned instead. This
+/// makes it usable as inclusive start and exclusive end of a date range.
+pub fn parse_day(day: &str, end_of_day: bool) -> Result<DateTime<Local>, String> {
+    let date = match NaiveDate::parse_from_str(day, "%Y-%m-%d") {
+        Ok(d) => d,
+        Err(_) => return Err(format!("invalid date '{}', use the format YYYY-MM-DD", day))
+    };
+    let date = if end_of_day { date + Duration::days(1) } else { date };
 
-                    let title = format!("{}", Cyan.underline().paint(e.title));
-                    print!("{:<40}", title);
+    Local.from_local_datetime(&date.and_hms(0, 0, 0)).earliest()
+        .ok_or_else(|| format!("date '{}' doesn't exist in the local timezone", day))
+}
 
-                    if date {
-                        print!("{} ", Cyan.paint(e.date.to_rfc2822().to_string()));
-                    }
+/// Parses a duration like `7d`, `2w`, `3m` or `1y` (days, weeks, months, years) and returns
+/// the point in time that long ago. Months and years keep the day of the month where possible.
+pub fn parse_relative(duration: &str) -> Result<DateTime<Local>, String> {
+    let invalid = || format!("invalid duration '{}', use a number followed by d, w, m or y like 7d", duration);
 
-                    if id {
-                        let id = format!("{}", Cyan.paint(format!("[{}]", e.id)));
-                        print!("{:<20}", id);
-                    }
+    let unit = duration.chars().last().ok_or_else(invalid)?;
+    let n: i64 = duration[..duration.len() - unit.len_utf8()].parse().map_err(|_| invalid())?;
+    if n < 0 {
+        return Err(invalid());
+    }
 
-                    if hash {
-                        let hash = format!("[{}]", hex::encode(&e.hash));
-                        print!("{:<30}", Cyan.paint(hash))
-                    }
+    let now = Local::now();
+    let months = match unit {
+        'd' => return Ok(now - Duration::days(n)),
+        'w' => return Ok(now - Duration::weeks(n)),
+        'm' => n,
+        'y' => n * 12,
+        _ => return Err(invalid())
+    };
 
-                    println!();
+    let total = now.year() as i64 * 12 + now.month0() as i64 - months;
+    let (year, month) = (total.div_euclid(12) as i32, total.rem_euclid(12) as u32 + 1);
+    // clamp the day to the length of the target month, e.g. 31st March minus 1m is 28th or 29th February
+    let date = (1..=now.day()).rev()
+        .find_map(|d| NaiveDate::from_ymd_opt(year, month, d))
+        .ok_or_else(invalid)?;
 
-                    if keywords {
-                        print!("Keywords: ");
+    Local.from_local_datetime(&date.and_time(now.time())).earliest()
+        .ok_or_else(|| format!("the time {} ago doesn't exist in the local timezone", duration))
+}
 
-                        let last = e.keywords.last().unwrap().clone();
-                        for k in e.keywords {
-                            print!("{}", Cyan.paint(&k));
-                            if k != last {
-                                print!(", ")
-                            }
-                        }
+/// Timezone dates can be shown in, either a fixed offset or a name of the IANA database
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timezone {
+    Fixed(FixedOffset),
+    /// Follows the daylight saving time of the zone, e.g. `Europe/Vienna`
+    Named(Tz),
+}
 
-                        println!();
-                    }
+impl Timezone {
+    /// The wall clock time of `date` in the timezone
+    fn naive_local(&self, date: &DateTime<Local>) -> NaiveDateTime {
+        match self {
+            Timezone::Fixed(tz) => date.with_timezone(tz).naive_local(),
+            Timezone::Named(tz) => date.with_timezone(tz).naive_local(),
+        }
+    }
 
-                    if content {
-                        println!("{}", e.content)
-                    }
+    /// Formats `date` in the timezone like RFC 2822
+    fn format(&self, date: &DateTime<Local>) -> String {
+        match self {
+            Timezone::Fixed(tz) => date.with_timezone(tz).to_rfc2822(),
+            Timezone::Named(tz) => date.with_timezone(tz).to_rfc2822(),
+        }
+    }
+}
 
-                    println!();
-                }
-                None => {
-                    if counter > 0 {
-                        println!("{:-<1$}", "", termsize::get().unwrap().cols as usize);
-                    }
-                    break;
-                }
+/// Parses a timezone given as `UTC`, as offset like `+02:00`, `-0530` or `+01` or as
+/// IANA name like `Europe/Vienna`
+pub fn parse_timezone(tz: &str) -> Result<Timezone, String> {
+    if tz.eq_ignore_ascii_case("utc") || tz == "Z" {
+        return Ok(Timezone::Fixed(FixedOffset::east(0)));
+    }
+
+    let invalid = || format!("invalid timezone '{}', use UTC, an offset like +02:00 or a name like Europe/Vienna", tz);
+
+    let (sign, rest) = match tz.chars().next() {
+        Some('+') => (1, &tz[1..]),
+        Some('-') => (-1, &tz[1..]),
+        _ => return tz.parse().map(Timezone::Named).map_err(|_| invalid())
+    };
+    let digits: String = rest.chars().filter(|c| *c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) || (digits.len() != 2 && digits.len() != 4) {
+        return Err(invalid());
+    }
+
+    let hours: i32 = digits[..2].parse().map_err(|_| invalid())?;
+    let minutes: i32 = if digits.len() == 4 { digits[2..].parse().map_err(|_| invalid())? } else { 0 };
+    if minutes >= 60 {
+        return Err(invalid());
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).map(Timezone::Fixed).ok_or_else(invalid)
+}
+
+/// Removes control characters except newlines and tabs from `text`, windows line endings
+/// are converted to plain newlines. Returns the cleaned text and the number of
+/// removed characters.
+pub fn sanitize_text(text: &str) -> (String, usize) {
+    let text = text.replace("\r\n", "\n");
+    let clean: String = text.chars()
+        .filter(|c| !c.is_control() || *c == '\n' || *c == '\t')
+        .collect();
+    let removed = text.chars().count() - clean.chars().count();
+
+    (clean, removed)
+}
+
+/// Finds pairs of keywords where the first one is contained in the second one,
+/// e.g. `work` and `working`. Exact duplicates are not reported.
+pub fn overlapping_keywords(keywords: &[String]) -> Vec<(String, String)> {
+    let mut overlapping = Vec::new();
+
+    for a in keywords {
+        for b in keywords {
+            if a != b && b.contains(a.as_str()) && !overlapping.contains(&(a.clone(), b.clone())) {
+                overlapping.push((a.clone(), b.clone()));
             }
         }
+    }
 
-        if counter == 1 {
-            println!("Found {} entry.", Cyan.paint(format!("{}", counter)));
-        } else {
-            println!("Found {} entries.", Cyan.paint(format!("{}", counter)));
+    overlapping
+}
+
+pub struct Diary {
+    connection: Connection,
+    /// Further databases combined with the main one, together with their names
+    others: Vec<(String, Connection)>,
+    /// Algorithm used for the hashes of the entries
+    hash_algorithm: HashAlgorithm,
+    /// Where listings get written to, `stdout` by default
+    out: Box<dyn Write>,
+    /// Width of the separators, `None` uses the width of the terminal
+    width: Option<usize>,
+    /// Maximum number of characters of the content of an entry
+    max_content_length: Option<usize>,
+}
+
+/// Version of the database schema, databases with an older version are migrated when opened
+const SCHEMA_VERSION: i64 = 4;
+
+/// Width of the separators when the terminal width is unknown
+const DEFAULT_WIDTH: usize = 80;
+
+/// Returns the width of the terminal, if stdout isn't a terminal `$COLUMNS` or `DEFAULT_WIDTH` is used
+fn terminal_width() -> usize {
+    match termsize::get() {
+        Some(size) if size.cols > 0 => size.cols as usize,
+        _ => std::env::var("COLUMNS").ok()
+            .and_then(|c| c.parse().ok())
+            .filter(|c| *c > 0)
+            .unwrap_or(DEFAULT_WIDTH)
+    }
+}
+
+/// How many actions are kept in the action log for `undo`
+const UNDO_LIMIT: i64 = 20;
+
+/// Name of the diary selected by the global `--diary` flag, `None` is the default diary
+static DIARY_NAME: OnceLock<String> = OnceLock::new();
+
+/// Selects the named diary `digital_diary_<name>.sqlite` instead of the default one
+pub fn select_diary(name: &str) {
+    DIARY_NAME.set(name.to_string()).unwrap();
+}
+
+/// Validates a diary name so it can be part of a file name
+pub fn validate_diary_name(name: &str) -> Result<(), String> {
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        Ok(())
+    } else {
+        Err(String::from("diary names may only contain letters, digits, '-' and '_'"))
+    }
+}
+
+impl Diary {
+    /// File name of the diary in the home directory, named diaries get their name appended
+    fn database_file_name(name: Option<&str>) -> String {
+        match name {
+            Some(n) => format!("digital_diary_{}.sqlite", n),
+            None => String::from("digital_diary.sqlite")
+        }
+    }
+
+    /// Checks that `path` can be used as database file, returns a message explaining how to fix it otherwise.
+    /// Write access isn't checked so read-only databases can still be read, SQLite reports it when
+    /// a command tries to change them.
+    fn check_database_path(path: &Path) -> Result<(), String> {
+        if path.is_dir() {
+            return Err(format!("'{}' is a directory, use a database file inside it like '{}'",
+                               path.display(), path.join("digital_diary.sqlite").display()));
+        }
+
+        if path.exists() {
+            return Ok(());
+        }
+
+        let parent = Self::parent_dir(path);
+        if !parent.is_dir() {
+            return Err(format!("the directory '{}' of the database doesn't exist, create it using `mkdir -p {}`",
+                               parent.display(), parent.display()));
         }
+
+        Ok(())
     }
 
-    /// Prints all entries, which and what gets printed can be customised using the
-    /// parameters.
-    pub fn list_all(&mut self, date: bool, id: bool, hash: bool, keywords: bool, content: bool,
-                    hidden: bool) {
-        let entries = self.get_entries();
-        Self::print_entries(entries, date, id, hash, keywords, content, hidden);
+    /// Directory the database file at `path` is in
+    fn parent_dir(path: &Path) -> &Path {
+        match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new(".")
+        }
     }
 
-    /// Searches through all entries and prints the one that match the search terms,
-    /// which and what gets printed can be customised using the parameters.
-    /// `searchfor` contains the words to search for, from every entry the keywords and the title will
-    /// be searched. `searchfor` has to be lowercase.
-    pub fn search(&mut self, searchfor: Vec<String>, date: bool, id: bool, hash: bool, keywords: bool,
-                  content: bool, hidden: bool) {
-        let entries = self.get_entries();
-        let mut found = Vec::new();
+    /// Checks that files can be created next to the database at `path` by creating and removing
+    /// a probe file, so `create` fails before asking instead of with an error of SQLite.
+    fn check_writable(path: &Path) -> Result<(), DiaryError> {
+        let parent = Self::parent_dir(path);
+        let probe = parent.join(format!(".didi-probe-{}", std::process::id()));
 
-        for e in &entries {
-            for s in &searchfor {
-                let mut jumpout = false;
+        std::fs::OpenOptions::new().write(true).create_new(true).open(&probe)
+            .and_then(|_| std::fs::remove_file(&probe))
+            .map_err(|e| DiaryError::Database(format!("can't create the database in '{}': {}", parent.display(), e)))
+    }
 
-                if e.title.to_lowercase().contains(s) {
-                    found.push(e.clone());
-                    break;
-                }
+    /// Whether `url` is an SQLite URI like `file:diary.sqlite?mode=ro`, these are passed
+    /// to SQLite as they are without checking the file system.
+    fn is_uri(url: &Path) -> bool {
+        url.to_str().is_some_and(|u| u.starts_with("file:"))
+    }
 
-                for k in &e.keywords {
-                    if k.contains(s) {
-                        found.push(e.clone());
-                        jumpout = true;
-                        break;
-                    }
-                }
+    fn get_database_url(expect_existence: bool) -> Result<PathBuf, DiaryError> {
+        if let Ok(a) = std::env::var("DIDI_URL") {
+            // only `list`, `search` and `stats` combine a comma separated list, all others use the first database
+            let p = PathBuf::from(a.split(',').next().unwrap());
+            if Self::is_uri(&p) {
+                return Ok(p);
+            }
+            Self::check_database_path(&p).map_err(|e| DiaryError::Database(format!("{} (DIDI_URL)", e)))?;
 
-                if jumpout {
-                    break;
-                }
+            if !p.exists() && expect_existence {
+                return Err(DiaryError::Database(
+                    String::from("database specified in DIDI_URL doesn't exist maybe use `didi create`")));
+            }
+
+            Ok(p)
+        } else {
+            let mut d = home_dir().ok_or_else(|| DiaryError::Database(String::from("couldn't retrieve home directory")))?;
+            d.push(Self::database_file_name(DIARY_NAME.get().map(|n| n.as_str())));
+            Self::check_database_path(&d).map_err(DiaryError::Database)?;
+
+            if !d.as_path().exists() && expect_existence {
+                return Err(DiaryError::Database(match DIARY_NAME.get() {
+                    Some(n) => format!("no database file found for diary '{}'. Use `didi --diary {} create`", n, n),
+                    None => String::from("no database file found. Specify DIDI_URL or use `didi create`")
+                }));
             }
+
+            Ok(d)
         }
+    }
 
-        Self::print_entries(found, date, id, hash, keywords, content, hidden);
+    /// Resolves the path of the database like `open` and `create` do, with `expect_existence`
+    /// it fails if there is no database there yet.
+    pub fn path(expect_existence: bool) -> Result<PathBuf, DiaryError> {
+        Self::get_database_url(expect_existence)
     }
 
-    /// Hides or unhides the entries given by `ids`.
-    /// The `set` parameter specifies if the entry should be hidden or not.
-    pub fn hide(&mut self, ids: Vec<i64>, set: bool) {
-        let mut counter = 0;
+    /// Finds the diaries in the home directory sorted by name, the default diary has no name
+    pub fn diaries() -> Result<Vec<(Option<String>, PathBuf)>, DiaryError> {
+        let home = home_dir().ok_or_else(|| DiaryError::Database(String::from("couldn't retrieve home directory")))?;
+        let files = std::fs::read_dir(&home).map_err(|e| {
+            DiaryError::Database(format!("couldn't read home directory '{}': {}", home.display(), e))
+        })?;
 
-        for i in ids {
-            match self.connection.execute(
-                r#"
-                UPDATE entries SET hidden = ?1 WHERE id = ?2
-                "#, params![set, i]) {
-                Err(e) => panic!("Error: couldn't update entry: {:?}", e),
-                Ok(n) => counter += n
+        let mut diaries: Vec<(Option<String>, PathBuf)> = files
+            .filter_map(|f| f.ok())
+            .filter_map(|f| {
+                let file_name = f.file_name().to_string_lossy().to_string();
+                let stem = file_name.strip_suffix(".sqlite")?;
+                if stem == "digital_diary" {
+                    return Some((None, f.path()));
+                }
+                let name = stem.strip_prefix("digital_diary_")?;
+                validate_diary_name(name).ok().map(|_| (Some(name.to_string()), f.path()))
+            })
+            .collect();
+        diaries.sort();
+        Ok(diaries)
+    }
+
+    /// Returns the databases given by `databases` or a comma separated list in `DIDI_URL`,
+    /// falls back to the single default database.
+    fn get_database_urls(databases: Option<Vec<String>>) -> Result<Vec<PathBuf>, DiaryError> {
+        let urls: Vec<PathBuf> = match databases {
+            Some(d) => d.into_iter().map(PathBuf::from).collect(),
+            None => match std::env::var("DIDI_URL") {
+                Ok(a) if a.contains(',') => a.split(',').map(PathBuf::from).collect(),
+                _ => return Ok(vec![Self::get_database_url(true)?])
+            }
+        };
+
+        for u in urls.iter().filter(|u| !Self::is_uri(u)) {
+            Self::check_database_path(u).map_err(DiaryError::Database)?;
+            if !u.exists() {
+                return Err(DiaryError::Database(format!("database '{}' doesn't exist", u.display())));
             }
         }
 
-        if counter == 1 {
-            println!("Changed {} entry.", Cyan.paint(format!("{}", counter)));
-        } else {
-            println!("Changed {} entries.", Cyan.paint(format!("{}", counter)));
+        Ok(urls)
+    }
+
+    fn connect(url: &PathBuf, flags: OpenFlags) -> Result<Connection, DiaryError> {
+        let connection = Connection::open_with_flags(url, flags)
+            .map_err(|e| DiaryError::Database(format!("couldn't open database '{}': {}", url.display(), e)))?;
+        // titles are sorted the same way in SQL and in `sort_entries`
+        connection.create_collation("normalized", |a, b| normalize(a).cmp(&normalize(b)))?;
+        Ok(connection)
+    }
+
+    /// Opens a connection to `url` and migrates the database
+    fn open_connection(url: &PathBuf) -> Result<Connection, DiaryError> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_URI;
+        let connection = Self::connect(url, flags)?;
+        Self::migrate(&connection)?;
+        Ok(connection)
+    }
+
+    /// Opens a connection to `url` which is only read from, the database isn't migrated
+    /// so it has to be at the current schema version already
+    fn open_read_only_connection(url: &PathBuf) -> Result<Connection, DiaryError> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_URI;
+        let connection = Self::connect(url, flags)?;
+
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version < SCHEMA_VERSION {
+            return Err(DiaryError::Database(format!(
+                "database '{}' has schema version {} but {} is needed, open it on its own once to migrate it",
+                url.display(), version, SCHEMA_VERSION)));
+        }
+        if version > SCHEMA_VERSION {
+            return Err(DiaryError::Database(format!(
+                "database '{}' has schema version {} but this version of didi only supports up to {}",
+                url.display(), version, SCHEMA_VERSION)));
+        }
+
+        Ok(connection)
+    }
+
+    pub fn open() -> Result<Self, DiaryError> {
+        let diary = Self::open_quietly()?;
+
+        let url = Self::get_database_url(true)?;
+        println!("Welcome {} at '{}'!\n", color(Cyan).paint(username()),
+                 color(Cyan).paint(url.as_path().to_str().unwrap()));
+
+        Ok(diary)
+    }
+
+    /// Opens the database like `open` without printing the welcome message, used when
+    /// the output of a command goes to `stdout` and mustn't contain anything else
+    pub fn open_quietly() -> Result<Self, DiaryError> {
+        let url = Self::get_database_url(true)?;
+        let connection = Self::open_connection(&url)?;
+        let hash_algorithm = Self::get_hash_algorithm(&connection)?;
+
+        Ok(Diary { connection, others: Vec::new(), hash_algorithm, out: Box::new(stdout()), width: None,
+                   max_content_length: None })
+    }
+
+    /// Opens one or more databases, their entries get combined in a single view.
+    /// `databases` overrides the databases given in `DIDI_URL`. Unless `writable` is set
+    /// all but the first database are opened read-only and aren't migrated.
+    pub fn open_combined(databases: Option<Vec<String>>, writable: bool) -> Result<Self, DiaryError> {
+        let diary = Self::open_combined_quietly(databases.clone(), writable)?;
+
+        let urls = Self::get_database_urls(databases)?;
+        let paths: Vec<&str> = urls.iter().map(|u| u.as_path().to_str().unwrap()).collect();
+        println!("Welcome {} at '{}'!\n", color(Cyan).paint(username()), color(Cyan).paint(paths.join(", ")));
+
+        Ok(diary)
+    }
+
+    /// Opens the databases like `open_combined` without printing the welcome message
+    pub fn open_combined_quietly(databases: Option<Vec<String>>, writable: bool) -> Result<Self, DiaryError> {
+        let urls = Self::get_database_urls(databases)?;
+
+        let mut connections = Vec::new();
+        for (i, u) in urls.iter().enumerate() {
+            let name = u.file_stem().map_or_else(|| u.display().to_string(),
+                                                 |s| s.to_string_lossy().to_string());
+            let connection = if i == 0 || writable {
+                Self::open_connection(u)?
+            } else {
+                Self::open_read_only_connection(u)?
+            };
+            connections.push((name, connection));
+        }
+        let (_, connection) = connections.remove(0);
+        let others = connections;
+
+        let hash_algorithm = Self::get_hash_algorithm(&connection)?;
+
+        Ok(Diary { connection, others, hash_algorithm, out: Box::new(stdout()), width: None,
+                   max_content_length: None })
+    }
+
+    /// When `list` or `search` last ran on the database, `None` if they never did
+    pub fn last_run(&self) -> Option<DateTime<Local>> {
+        let value: Option<String> = self.connection.query_row(
+            "SELECT value FROM meta WHERE key = 'last_run'", [], |row| row.get(0)).optional().ok().flatten();
+        value.and_then(|v| DateTime::parse_from_rfc3339(&v).ok()).map(|t| t.with_timezone(&Local))
+    }
+
+    /// Remembers `time` as the last run of `list` or `search`. Failures are ignored so
+    /// read-only databases can still be listed.
+    pub fn store_last_run(&self, time: DateTime<Local>) {
+        let _ = self.connection.execute("INSERT OR REPLACE INTO meta (key, value) VALUES ('last_run', ?1)",
+                                        params![time.to_rfc3339()]);
+    }
+
+    /// Writes listings into `out` instead of `stdout`, the separators get a fixed width.
+    pub fn set_output(&mut self, out: Box<dyn Write>) {
+        self.out = out;
+        self.width = Some(DEFAULT_WIDTH);
+    }
+
+    /// Writes listings to `stdout` again after `set_output`, the separators use the terminal width
+    pub fn reset_output(&mut self) {
+        self.out = Box::new(stdout());
+        self.width = None;
+    }
+
+    /// Limits how many characters the content of new or changed entries may have
+    pub fn set_max_content_length(&mut self, max: Option<usize>) {
+        self.max_content_length = max;
+    }
+
+    /// Checks that `title` isn't blank
+    pub fn check_title(title: &str) -> Result<(), String> {
+        if title.trim().is_empty() {
+            Err(String::from("the title of an entry can't be empty"))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that `content` doesn't exceed the maximum content length
+    pub fn check_content_length(&self, content: &str) -> Result<(), String> {
+        let length = content.chars().count();
+
+        match self.max_content_length {
+            Some(max) if length > max => {
+                Err(format!("content is {} characters long but at most {} are allowed", length, max))
+            }
+            _ => Ok(())
+        }
+    }
+
+    /// Creates the database, the hashes of the entries will be computed using `hash_algorithm`.
+    /// `accept` gets the resolved path before anything is created, returns `None` if it declines.
+    pub fn create<F>(hash_algorithm: HashAlgorithm, accept: F) -> Result<Option<PathBuf>, DiaryError>
+        where F: FnOnce(&Path) -> bool {
+        let url = Self::get_database_url(false)?;
+        if !Self::is_uri(&url) {
+            Self::check_writable(&url)?;
+        }
+        if !accept(&url) {
+            return Ok(None);
+        }
+
+        let connection = Self::connect(&url, OpenFlags::default())?;
+        Self::initialize(&connection, hash_algorithm)?;
+
+        Ok(Some(url))
+    }
+
+    /// Opens a new database which only exists in memory, used by the tests
+    #[cfg(test)]
+    pub fn open_in_memory() -> Self {
+        let connection = Self::connect(&PathBuf::from(":memory:"), OpenFlags::default()).unwrap();
+        Self::initialize(&connection, HashAlgorithm::Sha256).unwrap();
+
+        Diary { connection, others: Vec::new(), hash_algorithm: HashAlgorithm::Sha256, out: Box::new(stdout()),
+                width: None, max_content_length: None }
+    }
+
+    /// Creates the tables of an empty database and records the `hash_algorithm`
+    fn initialize(connection: &Connection, hash_algorithm: HashAlgorithm) -> Result<(), DiaryError> {
+        connection.execute(
+            r#"create table entries
+                (
+                    id       INTEGER not null,
+                    hash     BLOB    not null,
+                    date     TEXT    not null,
+                    keywords TEXT    not null,
+                    title    TEXT    not null,
+                    content  TEXT    not null,
+                    hidden   INTEGER not null,
+                    language TEXT,
+                    pinned   INTEGER not null default 0,
+                    primary key (id autoincrement),
+                    unique (id)
+                );"#, [])?;
+
+        Self::migrate(connection)?;
+
+        connection.execute(
+            "INSERT INTO meta (key, value) VALUES ('hash_algorithm', ?1)",
+            params![hash_algorithm.name()])?;
+
+        Ok(())
+    }
+
+    /// Reads the hash algorithm recorded in the database, databases created before it was
+    /// configurable use SHA-256.
+    fn get_hash_algorithm(connection: &Connection) -> Result<HashAlgorithm, DiaryError> {
+        let name = connection.query_row(
+            "SELECT value FROM meta WHERE key = 'hash_algorithm'", [],
+            |row| row.get::<_, String>(0)).optional()?;
+
+        match name {
+            Some(n) => HashAlgorithm::from_name(&n)
+                .ok_or_else(|| DiaryError::Database(format!("unknown hash algorithm '{}' in database", n))),
+            None => Ok(HashAlgorithm::Sha256)
+        }
+    }
+
+    /// Applies the migrations from the schema version stored in `PRAGMA user_version` up to
+    /// `SCHEMA_VERSION`, every step runs in its own transaction.
+    fn migrate(connection: &Connection) -> Result<(), DiaryError> {
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(DiaryError::Database(format!(
+                "the database has schema version {} but this version of didi only supports up to {}",
+                version, SCHEMA_VERSION)));
+        }
+
+        for v in version..SCHEMA_VERSION {
+            let tx = connection.unchecked_transaction()?;
+            match v {
+                0 => Self::migrate_unversioned(&tx)?,
+                1 => tx.execute_batch("ALTER TABLE entries ADD COLUMN attachments TEXT NOT NULL DEFAULT '';")?,
+                2 => tx.execute_batch("ALTER TABLE entries ADD COLUMN mood INTEGER;")?,
+                3 => Self::migrate_keywords(&tx)?,
+                _ => unreachable!()
+            }
+            tx.execute_batch(&format!("PRAGMA user_version = {}", v + 1))?;
+            tx.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Migration to version 1, adds the columns and tables introduced before the schema was
+    /// versioned to databases created by older versions. Every step checks whether it is needed.
+    fn migrate_unversioned(connection: &Connection) -> Result<(), DiaryError> {
+        if connection.prepare("SELECT language FROM entries").is_err() {
+            connection.execute("ALTER TABLE entries ADD COLUMN language TEXT", [])?;
+        }
+        if connection.prepare("SELECT pinned FROM entries").is_err() {
+            connection.execute("ALTER TABLE entries ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0", [])?;
+        }
+
+        connection.execute_batch(
+            r#"
+            CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+            CREATE TABLE IF NOT EXISTS review_queue (entry_id INTEGER PRIMARY KEY, added TEXT NOT NULL);
+            CREATE TABLE IF NOT EXISTS metadata
+            (
+                entry_id INTEGER NOT NULL,
+                key      TEXT    NOT NULL,
+                value    TEXT    NOT NULL,
+                primary key (entry_id, key)
+            );
+            CREATE TABLE IF NOT EXISTS action_log
+            (
+                id     INTEGER PRIMARY KEY AUTOINCREMENT,
+                action TEXT NOT NULL,
+                time   TEXT NOT NULL,
+                data   TEXT NOT NULL
+            );
+            "#)?;
+
+        // full-text index of title, keywords and content, filled once for existing databases
+        if connection.prepare("SELECT rowid FROM entries_fts").is_err() {
+            connection.execute_batch(
+                r#"
+                CREATE VIRTUAL TABLE entries_fts USING fts5(title, keywords, content);
+                INSERT INTO entries_fts (rowid, title, keywords, content)
+                SELECT id, title, replace(keywords, ';', ' '), content FROM entries;
+                "#)?;
+        }
+
+        Ok(())
+    }
+
+    /// Migration to version 4, removes the empty keywords left by stray separators like in `;work`.
+    /// Hashes which matched the old keywords are recomputed, mismatching hashes are kept as they are
+    /// so tampered entries still show up in `verify`.
+    fn migrate_keywords(connection: &Connection) -> Result<(), DiaryError> {
+        let hash_algorithm = Self::get_hash_algorithm(connection)?;
+        let mut stmt = connection.prepare(
+            "SELECT id, hash, date, keywords, title, content FROM entries \
+             WHERE keywords LIKE ';%' OR keywords LIKE '%;' OR keywords LIKE '%;;%'")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?, row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?, row.get::<_, String>(4)?, row.get::<_, String>(5)?))
+        })?.collect::<Result<Vec<_>, _>>()?;
+
+        for (id, hash, date, keywords, title, content) in rows {
+            let cleaned = Self::join_keywords(&Self::split_keywords(&keywords));
+            let hash = if hash == hash_algorithm.digest(&[&keywords, &title, &content, &date]) {
+                hash_algorithm.digest(&[&cleaned, &title, &content, &date])
+            } else {
+                hash
+            };
+
+            connection.execute("UPDATE entries SET keywords = ?1, hash = ?2 WHERE id = ?3",
+                               params![cleaned, hash, id])?;
+            connection.execute("UPDATE entries_fts SET keywords = ?1 WHERE rowid = ?2",
+                               params![cleaned.replace(';', " "), id])?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds an entry to the database, fails if the title is blank
+    ///
+    /// `keywords` have to be lowercase, `language` is the ISO 639-1 code of the content,
+    /// `metadata` are custom `key=value` pairs stored alongside the entry and `mood` ranges from 1 to 5
+    #[allow(clippy::too_many_arguments)]
+    pub fn add(&mut self, keywords: Vec<String>, title: String, content: String, language: Option<&str>,
+               metadata: Vec<(String, String)>, attachments: Vec<String>, mood: Option<u8>) -> Result<(), DiaryError> {
+        Self::check_title(&title).map_err(DiaryError::Invalid)?;
+        self.check_content_length(&content).map_err(DiaryError::Invalid)?;
+
+        let entry = Entry {
+            id: 0,
+            hash: Vec::new(),
+            date: Local::now(),
+            keywords,
+            title,
+            content,
+            hidden: false,
+            pinned: false,
+            language: language.map(|l| l.to_string()),
+            metadata,
+            attachments,
+            mood,
+            source: None,
+        };
+        self.insert(&entry)?;
+
+        println!("Added {}!", color(Cyan).paint(&entry.title));
+        Ok(())
+    }
+
+    /// Joins keywords the way they are stored, sorted and without duplicates or empty keywords
+    fn join_keywords(keywords: &[String]) -> String {
+        let mut keywords: Vec<String> = keywords.iter().filter(|k| !k.is_empty()).cloned().collect();
+        keywords.sort();
+        keywords.dedup();
+        keywords.join(";")
+    }
+
+    /// Splits stored keywords, an empty string means the entry has no keywords
+    fn split_keywords(keywords: &str) -> Vec<String> {
+        keywords.split(';').filter(|k| !k.is_empty()).map(|k| k.to_string()).collect()
+    }
+
+    /// Computes the hash of an entry from its keywords, title, content and date
+    fn hash_of(&self, e: &Entry) -> Vec<u8> {
+        self.hash_algorithm.digest(&[&Self::join_keywords(&e.keywords), &e.title, &e.content,
+                                     &e.date.to_rfc3339()])
+    }
+
+    /// Inserts `e` with a new id and a freshly computed hash, returns the new id
+    fn insert(&mut self, e: &Entry) -> Result<i64, DiaryError> {
+        let hash = self.hash_of(e);
+        Self::insert_row(&self.connection, e, &hash)
+    }
+
+    /// Inserts `e` into `connection` with the given `hash`, returns the new id
+    fn insert_row(connection: &Connection, e: &Entry, hash: &[u8]) -> Result<i64, DiaryError> {
+        let keywords_str = Self::join_keywords(&e.keywords);
+        let date = e.date.to_rfc3339();
+
+        connection.execute(
+            r#"
+            INSERT INTO entries (hash, date, keywords, title, content, hidden, language, attachments, mood) VALUES
+            (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            "#, params![hash, date, keywords_str, e.title, e.content, e.hidden, e.language, e.attachments.join(";"),
+                        e.mood])?;
+
+        let id = connection.last_insert_rowid();
+        connection.execute(
+            "INSERT INTO entries_fts (rowid, title, keywords, content) VALUES (?1, ?2, ?3, ?4)",
+            params![id, e.title, keywords_str.replace(';', " "), e.content])?;
+
+        for (key, value) in &e.metadata {
+            connection.execute(
+                "INSERT OR REPLACE INTO metadata (entry_id, key, value) VALUES (?1, ?2, ?3)",
+                params![id, key, value])?;
+        }
+
+        Ok(id)
+    }
+
+    /// Retrieves the metadata of all entries grouped by entry id
+    fn query_metadata(connection: &Connection) -> Result<HashMap<i64, Vec<(String, String)>>, DiaryError> {
+        let mut stmt = connection.prepare_cached("SELECT entry_id, key, value FROM metadata ORDER BY entry_id, key")?;
+
+        let mut metadata: HashMap<i64, Vec<(String, String)>> = HashMap::new();
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        for r in rows {
+            let (id, key, value) = r?;
+            metadata.entry(id).or_default().push((key, value));
+        }
+
+        Ok(metadata)
+    }
+
+    /// Retrieves all entries with an id greater than `after_id` from the database,
+    /// use `0` to retrieve every entry. The entries are sorted by `sort` and by date with the
+    /// newest first without it, ties are ordered by id. `reverse` flips the order. When multiple
+    /// databases are open the entries of all of them are combined.
+    fn get_entries(&mut self, after_id: i64, sort: Option<SortField>, reverse: bool) -> Result<Vec<Entry>, DiaryError> {
+        self.get_entries_where("id > ?1", &[&after_id], &order_by(sort, reverse, false), sort, reverse)
+    }
+
+    /// Retrieves the entries of every open database matching the `condition` with the `values` bound
+    /// to its parameters, each database sorts them by the `order_by` clause. The entries of combined
+    /// databases are merged and sorted by `sort` like `get_entries` does.
+    fn get_entries_where(&self, condition: &str, values: &[&dyn ToSql], order_by: &str, sort: Option<SortField>,
+                         reverse: bool) -> Result<Vec<Entry>, DiaryError> {
+        if self.others.is_empty() {
+            return Self::query_entries_where(&self.connection, None, condition, values, order_by);
+        }
+
+        let mut entries = Vec::new();
+        let name = self.name();
+        entries.append(&mut Self::query_entries_where(&self.connection, Some(&name), condition, values, order_by)?);
+
+        for (name, connection) in &self.others {
+            entries.append(&mut Self::query_entries_where(connection, Some(name), condition, values, order_by)?);
+        }
+
+        sort_entries(&mut entries, sort, reverse);
+        Ok(entries)
+    }
+
+    /// Counts the entries of every open database matching the `condition` with the `values` bound to its parameters
+    fn count_where(&self, condition: &str, values: &[&dyn ToSql]) -> Result<usize, DiaryError> {
+        let mut total = 0;
+        for connection in std::iter::once(&self.connection).chain(self.others.iter().map(|(_, c)| c)) {
+            let mut stmt = connection.prepare_cached(&format!("SELECT COUNT(*) FROM entries WHERE {}", condition))?;
+            total += stmt.query_row(values, |row| row.get::<_, i64>(0))? as usize;
+        }
+        Ok(total)
+    }
+
+    /// Name of the main database used to tell apart entries of combined databases
+    fn name(&self) -> String {
+        self.connection.path().and_then(|p| p.file_stem())
+            .map_or(String::new(), |s| s.to_string_lossy().to_string())
+    }
+
+    /// Finds the database of the entry `id` refers to together with the source name
+    /// its entries are tagged with. Without a source prefix the main database is used,
+    /// this is only allowed if there aren't any combined databases.
+    fn database_of(&self, id: &EntryId) -> Result<(Option<String>, &Connection), DiaryError> {
+        let main = (None, &self.connection);
+        match &id.source {
+            None if self.others.is_empty() => Ok(main),
+            None => Err(DiaryError::Invalid(format!("id {} is ambiguous when combining databases, prefix it with \
+                                                     the name of its database, e.g. `{}:{}`", id.id, self.name(), id.id))),
+            Some(s) if *s == self.name() && self.others.is_empty() => Ok(main),
+            Some(s) if *s == self.name() => Ok((Some(s.clone()), &self.connection)),
+            Some(s) => match self.others.iter().find(|(n, _)| n == s) {
+                Some((n, c)) => Ok((Some(n.clone()), c)),
+                None => Err(DiaryError::Invalid(format!("there is no database named '{}'", s)))
+            }
+        }
+    }
+
+    /// Tags every id with the source name of the database it resolves to like `database_of`
+    fn resolve_ids(&self, ids: &[EntryId]) -> Result<Vec<EntryId>, DiaryError> {
+        ids.iter().map(|i| Ok(EntryId { source: self.database_of(i)?.0, id: i.id })).collect()
+    }
+
+    /// Finds the database of the entry `id` refers to like `database_of`
+    fn connection_of(&self, id: &EntryId) -> Result<&Connection, DiaryError> {
+        Ok(self.database_of(id)?.1)
+    }
+
+    /// Retrieves the entries of a single database matching the `condition` with the `values`
+    /// bound to its parameters, the entries are tagged with the `source` name and sorted by
+    /// the `order_by` clause.
+    fn query_entries_where(connection: &Connection, source: Option<&str>, condition: &str, values: &[&dyn ToSql],
+                           order_by: &str) -> Result<Vec<Entry>, DiaryError> {
+        let mut metadata = Self::query_metadata(connection)?;
+        // cached since commands like the repl or show --related query the entries repeatedly
+        let mut stmt = connection.prepare_cached(&format!(
+            r#"
+            SELECT id, hash, date, keywords, title, content, hidden, language, pinned, attachments, mood FROM entries
+            WHERE {} {}
+            "#, condition, order_by))?;
+
+        let entries = stmt.query_map(values, |row| {
+            let id: i64 = row.get(0)?;
+            let hash = row.get(1)?;
+            let date = row.get(2)?;
+            let keywords = Self::split_keywords(&row.get::<_, String>(3)?);
+            let title = row.get(4)?;
+            let content = row.get(5)?;
+            let hidden = row.get(6)?;
+            let language = row.get(7)?;
+            let pinned = row.get(8)?;
+            let attachments: String = row.get(9)?;
+            let mood = row.get(10)?;
+
+            Ok(Entry {
+                id,
+                hash,
+                date,
+                keywords,
+                title,
+                content,
+                hidden,
+                pinned,
+                language,
+                metadata: metadata.remove(&id).unwrap_or_default(),
+                attachments: attachments.split(';').filter(|a| !a.is_empty()).map(|a| a.to_string()).collect(),
+                mood,
+                source: source.map(|s| s.to_string()),
+            })
+        })?.collect::<Result<Vec<Entry>, rusqlite::Error>>()?;
+        Ok(entries)
+    }
+
+    /// Prints the given entries, which and what gets printed can be customised using
+    /// `options`. `searchfor` are the terms the entries were searched for, it is empty
+    /// when listing. Pinned entries are printed first.
+    fn print_entries(&mut self, mut entries: Vec<Entry>, options: &DisplayOptions,
+                     searchfor: &[String]) -> Result<(), DiaryError> {
+        entries.sort_by_key(|e| !e.pinned);
+
+        // the entries were matched outside of the database, so they are paged here
+        let entries: Vec<Entry> = entries.into_iter().filter(|a| options.shows(a)).collect();
+        let total = entries.len();
+        let page = entries.into_iter()
+            .skip(options.offset.unwrap_or(0))
+            .take(options.limit.unwrap_or(usize::MAX))
+            .collect();
+
+        self.print_page(page, total, options, searchfor)
+    }
+
+    /// Prints the `page` of the `total` entries passing the filters of `options`, the page is
+    /// printed as given
+    fn print_page(&mut self, page: Vec<Entry>, total: usize, options: &DisplayOptions,
+                  searchfor: &[String]) -> Result<(), DiaryError> {
+        if options.json {
+            Self::write_json_entries(&mut self.out, page, total, options)?;
+            return Ok(());
+        }
+
+        let width = self.width.unwrap_or_else(terminal_width);
+        Self::write_entries(&mut self.out, width, page, total, options, searchfor)?;
+        Ok(())
+    }
+
+    fn write_entries(out: &mut dyn Write, width: usize, page: Vec<Entry>, total: usize, options: &DisplayOptions,
+                     searchfor: &[String]) -> std::io::Result<()> {
+        let now = Local::now();
+        let counter = page.len();
+
+        let groups = match options.group_by {
+            Some(g) => group_entries(page, g, options),
+            None => vec![(None, page)]
+        };
+
+        for (i, (header, group)) in groups.iter().enumerate() {
+            if let Some(h) = header {
+                if i > 0 {
+                    writeln!(out)?;
+                }
+                writeln!(out, "{}", color(Yellow).paint(format!("=== {} ===", h)))?;
+            }
+            for e in group {
+                Self::write_entry(out, width, e, options, searchfor, &now)?;
+            }
+            if !group.is_empty() && !options.summary {
+                writeln!(out, "{:-<1$}", "", width)?;
+            }
+        }
+
+        Self::write_summary(out, counter, total, options)
+    }
+
+    /// Writes a single entry, a block starting with a separator or a single line with `summary`
+    fn write_entry(out: &mut dyn Write, width: usize, e: &Entry, options: &DisplayOptions, searchfor: &[String],
+                   now: &DateTime<Local>) -> std::io::Result<()> {
+        if options.summary {
+            let id = format!("[{}]", e.display_id());
+            let mut date = options.format_date(&e.date);
+            if options.relative {
+                date = format!("{} ({})", date, relative_time(&e.date, now));
+            }
+            writeln!(out, "{} {} {}", color(Cyan).paint(format!("{:<8}", id)), color(Cyan).paint(date), e.title)?;
+            return Ok(());
+        }
+
+        writeln!(out, "{:-<1$}\n", "", width)?;
+
+        // padded by the width of the plain title, the escape codes of the highlighting take up no space
+        let padding = 40usize.saturating_sub(e.title.width());
+        write!(out, "{}{:2$}", highlight(&e.title, searchfor, color(Cyan.underline())), "", padding)?;
+
+        if options.date {
+            write!(out, "{} ", color(Cyan).paint(options.format_date(&e.date)))?;
+        }
+
+        if options.relative {
+            write!(out, "{} ", color(Cyan).paint(format!("({})", relative_time(&e.date, now))))?;
+        }
+
+        if options.id {
+            let id = format!("{}", color(Cyan).paint(format!("[{}]", e.display_id())));
+            write!(out, "{:<20}", id)?;
+        }
+
+        if options.hash {
+            let hash = format!("[{}]", hex::encode(&e.hash));
+            write!(out, "{:<30}", color(Cyan).paint(hash))?;
+        }
+
+        if options.language {
+            let language = format!("[{}]", e.language.as_deref().unwrap_or("?"));
+            write!(out, "{:<6}", color(Cyan).paint(language))?;
+        }
+
+        if options.word_count {
+            let n = e.word_count();
+            let words = if n == 1 { format!("[{} word]", n) } else { format!("[{} words]", n) };
+            write!(out, "{:<14}", color(Cyan).paint(words))?;
+        }
+
+        if e.pinned {
+            write!(out, "{} ", color(Yellow).paint("[pinned]"))?;
+        }
+
+        if options.match_count {
+            let n = e.count_matches(searchfor);
+            if n == 1 {
+                write!(out, "{}", color(Yellow).paint(format!("[{} match]", n)))?;
+            } else {
+                write!(out, "{}", color(Yellow).paint(format!("[{} matches]", n)))?;
+            }
+        }
+
+        writeln!(out)?;
+
+        if options.keywords {
+            let keywords: Vec<String> = e.keywords.iter()
+                .filter(|k| !k.trim().is_empty())
+                .map(|k| highlight(k, searchfor, color(Cyan)))
+                .collect();
+
+            if keywords.is_empty() {
+                writeln!(out, "Keywords: (none)")?;
+            } else {
+                writeln!(out, "Keywords: {}", keywords.join(", "))?;
+            }
+        }
+
+        if options.metadata && !e.metadata.is_empty() {
+            let metadata: Vec<String> = e.metadata.iter()
+                .map(|(k, v)| format!("{}", color(Cyan).paint(format!("{}={}", k, v))))
+                .collect();
+            writeln!(out, "Metadata: {}", metadata.join(", "))?;
+        }
+
+        if options.attachments && !e.attachments.is_empty() {
+            writeln!(out, "Attachments: {}", color(Cyan).paint(e.attachments.join(", ")))?;
+        }
+
+        if let (true, Some(m)) = (options.mood, e.mood) {
+            writeln!(out, "Mood: {}", color(Cyan).paint(format!("{}/5", m)))?;
+        }
+
+        if options.content {
+            let mut content = if options.compact { compact(&e.content) } else { e.content.clone() };
+            if let Some(n) = options.preview {
+                content = preview(&content, n);
+            }
+            writeln!(out, "{}", highlight(&content, searchfor, Style::new()))?;
+        }
+
+        writeln!(out)?;
+        Ok(())
+    }
+
+    /// Writes the filtered `entries` as a JSON array in the shape of `export --format json`,
+    /// the summary is written to `stderr` so `out` only contains JSON.
+    fn write_json_entries(out: &mut dyn Write, page: Vec<Entry>, total: usize,
+                          options: &DisplayOptions) -> std::io::Result<()> {
+        let values: Vec<Json> = page.iter().map(Self::to_json).collect();
+        let counter = values.len();
+
+        writeln!(out, "{}", Json::Array(values).serialize(false))?;
+        out.flush()?;
+        Self::write_summary(&mut std::io::stderr(), counter, total, options)
+    }
+
+    /// Writes how many of the `total` entries were found and how many of them were printed
+    fn write_summary(out: &mut dyn Write, counter: usize, total: usize, options: &DisplayOptions) -> std::io::Result<()> {
+        if let Some(offset) = options.offset {
+            let shown = if counter == 0 {
+                String::from("0")
+            } else {
+                format!("{}\u{2013}{}", offset + 1, offset + counter)
+            };
+            let noun = if total == 1 { "entry" } else { "entries" };
+            writeln!(out, "Showing {} of {} {}.", color(Cyan).paint(shown),
+                     color(Cyan).paint(format!("{}", total)), noun)?;
+            return Ok(());
+        }
+
+        if total == 1 {
+            writeln!(out, "Found {} entry.", color(Cyan).paint(format!("{}", total)))?;
+        } else {
+            writeln!(out, "Found {} entries.", color(Cyan).paint(format!("{}", total)))?;
+        }
+
+        if counter < total {
+            writeln!(out, "Only the first {} are shown, use {} to show all of them.",
+                     color(Cyan).paint(format!("{}", counter)), color(Cyan).paint("--all-results"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Prints all entries with an id greater than `after_id`, which and what gets printed
+    /// can be customised using `options`. If `every` is given only every nth entry in
+    /// date order gets printed.
+    pub fn list_all(&mut self, options: &DisplayOptions, after_id: i64, every: Option<usize>) -> Result<(), DiaryError> {
+        let (condition, values) = options.condition(after_id);
+        let values: Vec<&dyn ToSql> = values.iter().map(|v| v.as_ref()).collect();
+
+        if let Some(n) = every {
+            let order_by = order_by(Some(SortField::Date), false, false);
+            let mut entries = self.get_entries_where(&condition, &values, &order_by, Some(SortField::Date), false)?;
+            entries = entries.into_iter().step_by(n).collect();
+            sort_entries(&mut entries, options.sort, options.reverse);
+            return self.print_entries(entries, options, &[]);
+        }
+
+        let offset = options.offset.unwrap_or(0);
+        let combined = !self.others.is_empty();
+        // every combined database returns its first `offset + limit` entries, the page is taken after merging them
+        let (limit, skip) = match options.limit {
+            Some(l) if combined => ((offset + l) as i64, 0),
+            Some(l) => (l as i64, offset as i64),
+            // SQLite doesn't limit the rows for a negative limit
+            None => (-1, if combined { 0 } else { offset as i64 }),
+        };
+        let paged: Vec<&dyn ToSql> = values.iter().copied().chain([&limit as &dyn ToSql, &skip]).collect();
+        let order_by = format!("{} LIMIT ? OFFSET ?", order_by(options.sort, options.reverse, true));
+
+        let mut page = self.get_entries_where(&condition, &paged, &order_by, options.sort, options.reverse)?;
+        if combined {
+            page.sort_by_key(|e| !e.pinned);
+            page = page.into_iter().skip(offset).take(options.limit.unwrap_or(usize::MAX)).collect();
+        }
+        let total = self.count_where(&condition, &values)?;
+
+        self.print_page(page, total, options, &[])
+    }
+
+    /// Prints `n` randomly chosen non-hidden entries, the entries are picked by the database so
+    /// not all of them have to be loaded. What gets printed can be customised using `options`.
+    pub fn random(&mut self, n: usize, options: &DisplayOptions) -> Result<(), DiaryError> {
+        let limit = n as i64;
+        // the limit is bound so every `n` uses the same cached statement
+        let entries = Self::query_entries_where(&self.connection, None, "hidden = 0", &[&limit],
+                                                "ORDER BY RANDOM() LIMIT ?1")?;
+
+        self.print_entries(entries, options, &[])
+    }
+
+    /// Searches through all entries and prints the one that match the search terms,
+    /// which and what gets printed can be customised using `options`.
+    /// `searchfor` contains the words to search for, from every entry the keywords and the title will
+    /// be searched ignoring case and accents. With `phrase` every search term has to
+    /// match a whole keyword or a contiguous part of the title. With `all` an entry has
+    /// to match every search term instead of any of them. With `search_content` only the
+    /// content is searched instead of the title and keywords.
+    pub fn search(&mut self, searchfor: Vec<String>, options: &DisplayOptions, phrase: bool, all: bool,
+                  search_content: bool) -> Result<(), DiaryError> {
+        let entries = self.get_entries(0, options.sort, options.reverse)?;
+        let found = Self::matching(entries, &searchfor, phrase, all, search_content);
+
+        self.print_entries(found, options, &searchfor)
+    }
+
+    /// Prints the entries whose content matches any of the regular expressions in `patterns`,
+    /// with `all` it has to match every one of them. Fails if a pattern is invalid.
+    pub fn search_regex(&mut self, patterns: Vec<String>, options: &DisplayOptions, all: bool) -> Result<(), DiaryError> {
+        let regexes = patterns.iter()
+            .map(|p| Regex::new(p).map_err(|e| DiaryError::Invalid(format!("invalid regex '{}': {}", p, e))))
+            .collect::<Result<Vec<Regex>, DiaryError>>()?;
+
+        let found: Vec<Entry> = self.get_entries(0, options.sort, options.reverse)?.into_iter()
+            .filter(|e| {
+                if all {
+                    regexes.iter().all(|r| r.is_match(&e.content))
+                } else {
+                    regexes.iter().any(|r| r.is_match(&e.content))
+                }
+            })
+            .collect();
+
+        self.print_entries(found, options, &[])
+    }
+
+    /// Searches the title and keywords of all entries allowing other characters between the ones of
+    /// the `searchfor` terms and prints the entries best match first. Entries matching any term are
+    /// found, with `all` they have to match every term. Entries scoring below `threshold` are left out.
+    pub fn search_fuzzy(&mut self, searchfor: Vec<String>, options: &DisplayOptions, all: bool,
+                        threshold: i64) -> Result<(), DiaryError> {
+        let normalized: Vec<String> = searchfor.iter().map(|s| normalize(s)).collect();
+
+        let mut scored: Vec<(i64, Entry)> = self.get_entries(0, options.sort, options.reverse)?.into_iter()
+            .filter_map(|e| {
+                let title = normalize(&e.title);
+                let keywords: Vec<String> = e.keywords.iter().map(|k| normalize(k)).collect();
+
+                let scores: Vec<Option<i64>> = normalized.iter()
+                    .map(|s| std::iter::once(&title).chain(&keywords).filter_map(|t| fuzzy_score(s, t)).max())
+                    .collect();
+                let matched = if all { scores.iter().all(Option::is_some) } else { scores.iter().any(Option::is_some) };
+                let score = scores.iter().flatten().sum();
+
+                (matched && score >= threshold).then_some((score, e))
+            })
+            .collect();
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+
+        self.print_entries(scored.into_iter().map(|(_, e)| e).collect(), options, &searchfor)
+    }
+
+    /// Returns the `entries` whose title or keywords match any of the `searchfor` terms like `search` does,
+    /// with `all` they have to match every term. With `search_content` only the content is matched.
+    fn matching(entries: Vec<Entry>, searchfor: &[String], phrase: bool, all: bool, search_content: bool) -> Vec<Entry> {
+        let normalized: Vec<String> = searchfor.iter().map(|s| normalize(s)).collect();
+
+        // every entry is checked once, so an entry matched by several terms is still only found once
+        entries.into_iter().filter(|e| {
+            let title = normalize(&e.title);
+            let keywords: Vec<String> = e.keywords.iter().map(|k| normalize(k)).collect();
+            let content = if search_content { normalize(&e.content) } else { String::new() };
+
+            let matches = |s: &String| {
+                if search_content {
+                    return content.contains(s.as_str());
+                }
+                title.contains(s.as_str())
+                    || keywords.iter().any(|k| (phrase && k == s) || (!phrase && k.contains(s.as_str())))
+            };
+
+            if all {
+                normalized.iter().all(matches)
+            } else {
+                normalized.iter().any(matches)
+            }
+        }).collect()
+    }
+
+    /// Counts the entries, hidden ones only if `include_hidden` is `true`
+    pub fn count(&mut self, include_hidden: bool) -> Result<i64, DiaryError> {
+        let sql = if include_hidden {
+            "SELECT COUNT(*) FROM entries"
+        } else {
+            "SELECT COUNT(*) FROM entries WHERE hidden = 0"
+        };
+
+        Ok(self.connection.query_row(sql, [], |row| row.get(0))?)
+    }
+
+    /// Counts the entries matching the `searchfor` terms like `search`, hidden ones only if `include_hidden` is `true`
+    pub fn count_matching(&mut self, searchfor: &[String], include_hidden: bool) -> Result<usize, DiaryError> {
+        let entries = self.get_entries(0, None, false)?.into_iter()
+            .filter(|e| !e.hidden || include_hidden)
+            .collect();
+        Ok(Self::matching(entries, searchfor, false, false, false).len())
+    }
+
+    /// Searches the title, keywords and content of all entries using the full-text index and
+    /// prints the entries matching any of the `searchfor` terms, with `all` they have to match
+    /// every term. Each term is matched as a phrase.
+    pub fn search_fulltext(&mut self, searchfor: Vec<String>, options: &DisplayOptions,
+                           all: bool) -> Result<(), DiaryError> {
+        let query = searchfor.iter()
+            .map(|s| format!("\"{}\"", s.replace('"', "\"\"")))
+            .collect::<Vec<String>>()
+            .join(if all { " AND " } else { " OR " });
+
+        let mut matching: HashSet<(Option<String>, i64)> = HashSet::new();
+        let main = if self.others.is_empty() { None } else { Some(self.name()) };
+        for (source, connection) in std::iter::once((main, &self.connection))
+            .chain(self.others.iter().map(|(n, c)| (Some(n.clone()), c))) {
+            let mut stmt = connection.prepare("SELECT rowid FROM entries_fts WHERE entries_fts MATCH ?1")?;
+            for id in stmt.query_map(params![query], |row| row.get::<_, i64>(0))? {
+                matching.insert((source.clone(), id?));
+            }
+        }
+
+        let found: Vec<Entry> = self.get_entries(0, options.sort, options.reverse)?.into_iter()
+            .filter(|e| matching.contains(&(e.source.clone(), e.id)))
+            .collect();
+
+        self.print_entries(found, options, &searchfor)
+    }
+
+    /// Retrieves the entry with the given `id`, `None` if there is none
+    pub fn get_entry(&mut self, id: i64) -> Result<Option<Entry>, DiaryError> {
+        Ok(Self::query_entries_where(&self.connection, None, "id = ?1", &[&id], "")?.into_iter().next())
+    }
+
+    /// Retrieves the entries with the given `ids` in the order of `ids`, unknown ids are skipped.
+    /// Every database holding some of the ids is queried once.
+    pub fn get_entries_by_ids(&mut self, ids: &[EntryId]) -> Result<Vec<Entry>, DiaryError> {
+        let ids = self.resolve_ids(ids)?;
+        let mut groups: Vec<(Option<String>, Vec<i64>)> = Vec::new();
+        for id in &ids {
+            match groups.iter_mut().find(|(s, _)| *s == id.source) {
+                Some((_, g)) => g.push(id.id),
+                None => groups.push((id.source.clone(), vec![id.id]))
+            }
+        }
+
+        let mut entries = Vec::new();
+        for (source, group) in &groups {
+            let (_, connection) = self.database_of(&EntryId { source: source.clone(), id: group[0] })?;
+            let placeholders = vec!["?"; group.len()].join(", ");
+            let values: Vec<&dyn ToSql> = group.iter().map(|i| i as &dyn ToSql).collect();
+            entries.append(&mut Self::query_entries_where(connection, source.as_deref(),
+                                                          &format!("id IN ({})", placeholders), &values, "")?);
+        }
+
+        entries.sort_by_key(|e| ids.iter().position(|i| i.id == e.id && i.source == e.source));
+        Ok(entries)
+    }
+
+    /// Replaces the keywords, title and content of the entry with the given `id` and
+    /// recomputes its hash, the date of the entry is preserved. `keywords` have to be lowercase.
+    pub fn update(&mut self, id: i64, keywords: Vec<String>, title: String, content: String) -> Result<(), DiaryError> {
+        self.check_content_length(&content).map_err(DiaryError::Invalid)?;
+
+        let keywords_str = Self::join_keywords(&keywords);
+
+        // the raw date string is hashed so it doesn't change its representation
+        let date: String = self.connection.query_row(
+            "SELECT date FROM entries WHERE id = ?1", params![id], |row| row.get(0)).optional()?
+            .ok_or_else(|| DiaryError::Invalid(format!("there is no entry with id {}", id)))?;
+
+        let hash = self.hash_algorithm.digest(&[&keywords_str, &title, &content, &date]);
+
+        self.connection.execute(
+            "UPDATE entries SET keywords = ?1, title = ?2, content = ?3, hash = ?4 WHERE id = ?5",
+            params![keywords_str, title, content, hash, id])?;
+
+        self.connection.execute(
+            "UPDATE entries_fts SET title = ?1, keywords = ?2, content = ?3 WHERE rowid = ?4",
+            params![title, keywords_str.replace(';', " "), content, id])?;
+        Ok(())
+    }
+
+    /// Replaces the keywords of the entry with the given `id` and recomputes its hash,
+    /// the date of the entry is preserved. `keywords` have to be lowercase.
+    fn set_keywords(&mut self, id: i64, keywords: Vec<String>) -> Result<(), DiaryError> {
+        let e = self.get_entry(id)?.ok_or_else(|| DiaryError::Invalid(format!("there is no entry with id {}", id)))?;
+
+        self.update(id, keywords, e.title, e.content)
+    }
+
+    /// Links the files at `paths` to the entry with the given `id` in addition to its current attachments
+    pub fn attach(&mut self, id: i64, paths: &[String]) -> Result<(), DiaryError> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let e = self.get_entry(id)?.ok_or_else(|| DiaryError::Invalid(format!("there is no entry with id {}", id)))?;
+
+        let mut attachments = e.attachments;
+        attachments.extend(paths.iter().filter(|p| !attachments.contains(p)).cloned().collect::<Vec<String>>());
+
+        self.connection.execute(
+            "UPDATE entries SET attachments = ?1 WHERE id = ?2", params![attachments.join(";"), id])?;
+        Ok(())
+    }
+
+    /// Sets the mood of the entry with the given `id`, from 1 to 5
+    pub fn set_mood(&mut self, id: i64, mood: u8) -> Result<(), DiaryError> {
+        self.connection.execute("UPDATE entries SET mood = ?1 WHERE id = ?2", params![mood, id])?;
+        Ok(())
+    }
+
+    /// Adds the keywords in `add` to the entry with the given `id` and removes the ones in `remove`,
+    /// the content stays untouched. Returns the resulting keywords.
+    pub fn retag(&mut self, id: i64, add: Vec<String>, remove: Vec<String>) -> Result<Vec<String>, DiaryError> {
+        let e = self.get_entry(id)?.ok_or_else(|| DiaryError::Invalid(format!("there is no entry with id {}", id)))?;
+
+        let remove: Vec<String> = remove.iter().map(|k| k.to_lowercase()).collect();
+        let mut keywords: Vec<String> = e.keywords.into_iter()
+            .chain(add.iter().map(|k| k.to_lowercase()))
+            .filter(|k| !k.is_empty() && !remove.contains(k))
+            .collect();
+        keywords.sort();
+        keywords.dedup();
+
+        self.update(id, keywords.clone(), e.title, e.content)?;
+        Ok(keywords)
+    }
+
+    /// Suggests keywords for every entry without any, based on the most frequent words of
+    /// its content. `accept` decides whether the suggested keywords get stored.
+    pub fn rebuild_keywords(&mut self, mut accept: impl FnMut(&[String]) -> bool) -> Result<(), DiaryError> {
+        let mut counter = 0;
+        let untagged: Vec<Entry> = self.get_entries(0, Some(SortField::Id), false)?.into_iter()
+            .filter(|e| e.keywords.iter().all(|k| k.is_empty()))
+            .collect();
+
+        for e in untagged {
+            let suggested = salient_words(&e.content, 5);
+            if suggested.is_empty() {
+                continue;
+            }
+
+            println!("{} {}", color(Cyan.underline()).paint(&e.title), color(Cyan).paint(format!("[{}]", e.id)));
+            println!("Suggested keywords: {}", color(Cyan).paint(suggested.join(", ")));
+
+            if accept(&suggested) {
+                self.set_keywords(e.id, suggested)?;
+                counter += 1;
+            }
+            println!();
+        }
+
+        if counter == 1 {
+            println!("Tagged {} entry.", color(Cyan).paint(format!("{}", counter)));
+        } else {
+            println!("Tagged {} entries.", color(Cyan).paint(format!("{}", counter)));
+        }
+        Ok(())
+    }
+
+    /// Recomputes the hash of every entry from its keywords, title, content and date and
+    /// returns the entries where it doesn't match the stored hash.
+    fn hash_mismatches(&mut self) -> Result<Vec<HashMismatch>, DiaryError> {
+        let rows: Vec<(i64, Vec<u8>, String, String, String, String)> = {
+            let mut stmt = self.connection.prepare("SELECT id, hash, keywords, title, content, date FROM entries")?;
+
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+            })?;
+            rows.collect::<Result<_, rusqlite::Error>>()?
+        };
+
+        let mut mismatches = Vec::new();
+        let mut progress = Progress::new("Verifying", rows.len());
+
+        for (id, stored, keywords, title, content, date) in rows {
+            let computed = self.hash_algorithm.digest(&[&keywords, &title, &content, &date]);
+            progress.inc();
+
+            if stored != computed {
+                mismatches.push(HashMismatch {
+                    id,
+                    title: title.clone(),
+                    stored,
+                    computed,
+                    inputs: [("keywords", keywords), ("title", title), ("content", content), ("date", date)],
+                });
+            }
+        }
+
+        Ok(mismatches)
+    }
+
+    /// Checks the integrity of all entries by recomputing their hashes.
+    /// Returns the ids of the entries whose hashes don't match.
+    pub fn verify(&mut self) -> Result<Vec<i64>, DiaryError> {
+        Ok(self.hash_mismatches()?.into_iter().map(|m| m.id).collect())
+    }
+
+    /// Shows every entry whose hash doesn't match together with the fields the hash is
+    /// computed from. If `accept` returns `true` the recomputed hash gets stored,
+    /// otherwise the entry stays flagged. Returns the number of repaired entries.
+    pub fn verify_interactive(&mut self, mut accept: impl FnMut(i64) -> bool) -> Result<usize, DiaryError> {
+        let mut counter = 0;
+
+        for m in self.hash_mismatches()? {
+            println!("{} {}", color(Cyan.underline()).paint(&m.title), color(Cyan).paint(format!("[{}]", m.id)));
+            println!("stored hash:     {}", color(Red).paint(hex::encode(&m.stored)));
+            println!("recomputed hash: {}", color(Green).paint(hex::encode(&m.computed)));
+            for (name, value) in &m.inputs {
+                println!("{:<9} {}", format!("{}:", name), value);
+            }
+
+            if accept(m.id) {
+                self.connection.execute("UPDATE entries SET hash = ?1 WHERE id = ?2", params![m.computed, m.id])?;
+                counter += 1;
+            }
+            println!();
+        }
+
+        Ok(counter)
+    }
+
+    /// Groups `entries` by their keywords, the keywords are sorted alphabetically
+    fn tally_keywords(entries: &[Entry]) -> BTreeMap<&str, Vec<&Entry>> {
+        let mut tally: BTreeMap<&str, Vec<&Entry>> = BTreeMap::new();
+
+        for e in entries {
+            for k in e.keywords.iter().filter(|k| !k.is_empty()) {
+                tally.entry(k).or_default().push(e);
+            }
+        }
+
+        tally
+    }
+
+    /// Counts how many non-hidden entries use each keyword, sorted by the count in descending
+    /// order and alphabetically for equal counts.
+    pub fn keyword_histogram(&mut self) -> Result<Vec<(String, usize)>, DiaryError> {
+        let entries: Vec<Entry> = self.get_entries(0, None, false)?.into_iter()
+            .filter(|e| !e.hidden)
+            .collect();
+
+        let mut histogram: Vec<(String, usize)> = Self::tally_keywords(&entries).into_iter()
+            .map(|(k, e)| (k.to_string(), e.len()))
+            .collect();
+        histogram.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(histogram)
+    }
+
+    /// Prints every keyword which is used by exactly one entry together with that entry,
+    /// these are often typos or one-off tags.
+    pub fn orphan_keywords(&mut self) -> Result<(), DiaryError> {
+        let entries = self.get_entries(0, None, false)?;
+        let orphans: Vec<(&str, &Entry)> = Self::tally_keywords(&entries).into_iter()
+            .filter(|(_, e)| e.len() == 1)
+            .map(|(k, e)| (k, e[0]))
+            .collect();
+
+        let width = orphans.iter().map(|(k, _)| k.chars().count()).max().unwrap_or(0);
+        for (k, e) in &orphans {
+            println!("{:<width$} {} {}", k, color(Cyan).paint(format!("[{}]", e.display_id())), e.title,
+                     width = width);
+        }
+
+        if orphans.len() == 1 {
+            println!("\nFound {} orphan keyword.", color(Cyan).paint(format!("{}", orphans.len())));
+        } else {
+            println!("\nFound {} orphan keywords.", color(Cyan).paint(format!("{}", orphans.len())));
+        }
+        Ok(())
+    }
+
+    /// Prints an overview of the diary: number of entries, words, the first and last date,
+    /// the mood and the most used keywords. Hidden entries are included.
+    pub fn stats(&mut self) -> Result<(), DiaryError> {
+        let entries = self.get_entries(0, None, false)?;
+
+        let hidden = entries.iter().filter(|e| e.hidden).count();
+        let words: usize = entries.iter().map(Entry::word_count).sum();
+        let average = if entries.is_empty() { 0.0 } else { words as f64 / entries.len() as f64 };
+        let first = entries.iter().map(|e| e.date).min();
+        let last = entries.iter().map(|e| e.date).max();
+
+        let number = |n: usize| color(Cyan).paint(format!("{}", n));
+        println!("Entries:           {}", number(entries.len()));
+        println!("Hidden entries:    {}", number(hidden));
+        println!("Words:             {}", number(words));
+        println!("Words per entry:   {}", color(Cyan).paint(format!("{:.1}", average)));
+        if let (Some(first), Some(last)) = (first, last) {
+            println!("First entry:       {}", color(Cyan).paint(first.to_rfc2822()));
+            println!("Last entry:        {}", color(Cyan).paint(last.to_rfc2822()));
+        }
+
+        let moods: Vec<u8> = entries.iter().filter_map(|e| e.mood).collect();
+        if !moods.is_empty() {
+            let average = moods.iter().map(|&m| m as f64).sum::<f64>() / moods.len() as f64;
+            println!("Average mood:      {}", color(Cyan).paint(format!("{:.1}", average)));
+            println!("\nMood:");
+            let most = (1..=5).map(|m| moods.iter().filter(|&&n| n == m).count()).max().unwrap_or(0);
+            for m in (1..=5).rev() {
+                let n = moods.iter().filter(|&&n| n == m).count();
+                // bars are at most 30 characters wide
+                let bar = "█".repeat((n * 30).div_ceil(most.max(1)));
+                println!("  {} {} {}", m, color(Green).paint(bar), number(n));
+            }
+        }
+
+        let mut keywords: Vec<(&str, usize)> = Self::tally_keywords(&entries).into_iter()
+            .map(|(k, e)| (k, e.len()))
+            .collect();
+        keywords.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        if !keywords.is_empty() {
+            println!("\nTop keywords:");
+            let width = keywords.iter().take(10).map(|(k, _)| k.chars().count()).max().unwrap_or(0);
+            for (k, n) in keywords.iter().take(10) {
+                println!("  {:<width$} {}", k, number(*n), width = width);
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints a heatmap of how many entries were written on each day of `year`, with a row
+    /// per weekday and a column per week like a contribution graph.
+    pub fn calendar(&mut self, year: i32) -> Result<(), DiaryError> {
+        const SHADES: [&str; 5] = ["·", "░", "▒", "▓", "█"];
+
+        let first = NaiveDate::from_ymd_opt(year, 1, 1)
+            .ok_or_else(|| DiaryError::Invalid(format!("invalid year {}", year)))?;
+
+        let mut counts: HashMap<NaiveDate, usize> = HashMap::new();
+        for e in self.get_entries(0, None, false)? {
+            if e.date.year() == year {
+                *counts.entry(e.date.naive_local().date()).or_default() += 1;
+            }
+        }
+
+        let offset = first.weekday().num_days_from_monday() as usize;
+        let column = |d: NaiveDate| (d.ordinal0() as usize + offset) / 7;
+        let days: Vec<NaiveDate> = (0..366)
+            .filter_map(|i| first.checked_add_signed(Duration::days(i)))
+            .take_while(|d| d.year() == year)
+            .collect();
+        let columns = column(*days.last().unwrap()) + 1;
+
+        let mut grid = vec![vec![None; columns]; 7];
+        for d in &days {
+            grid[d.weekday().num_days_from_monday() as usize][column(*d)] = Some(counts.get(d).copied().unwrap_or(0));
+        }
+
+        let mut months = vec![' '; columns];
+        for m in 1..=12 {
+            let start = column(NaiveDate::from_ymd_opt(year, m, 1).unwrap());
+            let name = NaiveDate::from_ymd_opt(year, m, 1).unwrap().format("%b").to_string();
+            if start + name.len() <= columns && months[start.saturating_sub(1)..start + name.len()].iter().all(|c| *c == ' ') {
+                for (i, c) in name.chars().enumerate() {
+                    months[start + i] = c;
+                }
+            }
+        }
+
+        println!("    {}", months.into_iter().collect::<String>());
+        for (weekday, row) in ["Mon", "", "Wed", "", "Fri", "", "Sun"].iter().zip(&grid) {
+            let cells: String = row.iter().map(|c| match c {
+                Some(0) => format!("{}", color(Fixed(240)).paint(SHADES[0])),
+                Some(n) => format!("{}", color(Green).paint(SHADES[(*n).min(4)])),
+                None => String::from(" ")
+            }).collect();
+            println!("{:<4}{}", weekday, cells);
+        }
+
+        let total: usize = counts.values().sum();
+        let legend: Vec<String> = SHADES.iter().enumerate()
+            .map(|(i, s)| format!("{}", if i == 0 { color(Fixed(240)).paint(*s) } else { color(Green).paint(*s) }))
+            .collect();
+        println!("\n    Less {} More", legend.join(" "));
+        println!("\n{} {} in {} on {} {}.", color(Cyan).paint(format!("{}", total)),
+                 if total == 1 { "entry" } else { "entries" }, year,
+                 color(Cyan).paint(format!("{}", counts.len())), if counts.len() == 1 { "day" } else { "days" });
+        Ok(())
+    }
+
+    /// Returns the ids of all entries tagged with `keyword`, which has to be lowercase.
+    pub fn ids_with_keyword(&mut self, keyword: &str) -> Result<Vec<EntryId>, DiaryError> {
+        Ok(self.get_entries(0, None, false)?.into_iter()
+            .filter(|e| e.keywords.iter().any(|k| k == keyword))
+            .map(|e| EntryId { source: e.source, id: e.id })
+            .collect())
+    }
+
+    /// Pins or unpins the entries given by `ids`, pinned entries are listed before all others.
+    /// Ids which don't exist are ignored.
+    pub fn set_pinned(&mut self, ids: Vec<i64>, pinned: bool) -> Result<(), DiaryError> {
+        let counter = self.execute_for_ids("UPDATE entries SET pinned = ?2 WHERE id = ?1", &ids, &[&pinned])?;
+
+        if counter == 1 {
+            println!("Changed {} entry.", color(Cyan).paint(format!("{}", counter)));
+        } else {
+            println!("Changed {} entries.", color(Cyan).paint(format!("{}", counter)));
+        }
+        Ok(())
+    }
+
+    /// Runs `sql` once for every id in `ids` inside a single transaction using one prepared statement,
+    /// `?1` is the id followed by the `values`. Returns the number of changed rows.
+    fn execute_for_ids(&mut self, sql: &str, ids: &[i64], values: &[&dyn ToSql]) -> Result<usize, DiaryError> {
+        let tx = self.connection.transaction()?;
+
+        let mut counter = 0;
+        {
+            let mut stmt = tx.prepare(sql)?;
+            for i in ids {
+                let params: Vec<&dyn ToSql> = std::iter::once(i as &dyn ToSql).chain(values.iter().copied()).collect();
+                counter += stmt.execute(&*params)?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(counter)
+    }
+
+    /// Adds the entries given by `ids` to the review queue, ids which don't exist
+    /// or are already queued are ignored.
+    pub fn review_add(&mut self, ids: Vec<i64>) -> Result<(), DiaryError> {
+        let counter = self.execute_for_ids(
+            r#"
+            INSERT OR IGNORE INTO review_queue (entry_id, added)
+            SELECT id, ?2 FROM entries WHERE id = ?1
+            "#, &ids, &[&Local::now().to_rfc3339()])?;
+
+        if counter == 1 {
+            println!("Queued {} entry for review.", color(Cyan).paint(format!("{}", counter)));
+        } else {
+            println!("Queued {} entries for review.", color(Cyan).paint(format!("{}", counter)));
+        }
+        Ok(())
+    }
+
+    /// Removes the entries given by `ids` from the review queue.
+    pub fn review_done(&mut self, ids: Vec<i64>) -> Result<(), DiaryError> {
+        let counter = self.execute_for_ids("DELETE FROM review_queue WHERE entry_id = ?1", &ids, &[])?;
+
+        if counter == 1 {
+            println!("Reviewed {} entry.", color(Cyan).paint(format!("{}", counter)));
+        } else {
+            println!("Reviewed {} entries.", color(Cyan).paint(format!("{}", counter)));
+        }
+        Ok(())
+    }
+
+    /// Prints all entries in the review queue in the order they were queued,
+    /// which and what gets printed can be customised using `options`.
+    pub fn review_list(&mut self, options: &DisplayOptions) -> Result<(), DiaryError> {
+        let ids: Vec<i64> = {
+            let mut stmt = self.connection.prepare("SELECT entry_id FROM review_queue ORDER BY added")?;
+            let ids = stmt.query_map([], |row| row.get(0))?;
+            ids.collect::<Result<_, rusqlite::Error>>()?
+        };
+
+        let mut entries = self.get_entries(0, None, false)?;
+        entries.retain(|e| ids.contains(&e.id));
+        entries.sort_by_key(|e| ids.iter().position(|i| *i == e.id));
+
+        self.print_entries(entries, options, &[])
+    }
+
+    /// Prints the entries with the given `ids`, unknown ids are reported. If `related` is given
+    /// and a single entry is shown up to that many other entries which share the most keywords
+    /// with it are listed afterwards.
+    pub fn show(&mut self, ids: &[EntryId], options: &DisplayOptions, related: Option<usize>) -> Result<(), DiaryError> {
+        let shown = self.get_entries_by_ids(ids)?;
+        for id in ids {
+            let source = self.database_of(id)?.0;
+            match shown.iter().find(|e| e.id == id.id && e.source == source) {
+                None => println!("No entry with id {}", id),
+                Some(e) if !options.shows(e) && e.hidden => {
+                    println!("Entry {} is hidden, use --hidden to show it", id)
+                }
+                Some(e) if !options.shows(e) => println!("Entry {} is left out by the display flags", id),
+                Some(_) => {}
+            }
+        }
+
+        let entry = match (related, shown.as_slice()) {
+            (Some(_), [entry]) => entry.clone(),
+            _ => return self.print_entries(shown, options, &[])
+        };
+        let mut entries = self.get_entries(0, None, false)?;
+        entries.retain(|e| e.id != entry.id || e.source != entry.source);
+
+        let mut scored: Vec<(usize, Entry)> = entries.into_iter()
+            .filter(|e| options.shows(e))
+            .map(|e| {
+                let shared = e.keywords.iter().filter(|k| !k.is_empty() && entry.keywords.contains(k)).count();
+                (shared, e)
+            })
+            .filter(|(shared, _)| *shared > 0)
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.date.cmp(&a.1.date)));
+
+        self.print_entries(vec![entry], options, &[])?;
+
+        if let Some(n) = related {
+            writeln!(self.out, "\nRelated entries:")?;
+            for (shared, e) in scored.iter().take(n) {
+                let id = format!("[{}]", e.display_id());
+                writeln!(self.out, "{} {} {} ({} shared)", color(Cyan).paint(format!("{:<8}", id)),
+                         color(Cyan).paint(options.format_date(&e.date)), e.title, shared)?;
+            }
+            if scored.is_empty() {
+                writeln!(self.out, "None found.")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the ids of all entries written at or after `from` and before `to`,
+    /// a missing boundary isn't checked.
+    pub fn ids_in_range(&mut self, from: Option<DateTime<Local>>,
+                        to: Option<DateTime<Local>>) -> Result<Vec<EntryId>, DiaryError> {
+        Ok(self.get_entries(0, None, false)?.into_iter()
+            .filter(|e| from.is_none_or(|f| e.date >= f) && to.is_none_or(|t| e.date < t))
+            .map(|e| EntryId { source: e.source, id: e.id })
+            .collect())
+    }
+
+    /// Prints the existing entries given by `ids` including hidden ones with the fields chosen
+    /// by `options`, used to show what a dry run would change. Returns the number of entries.
+    fn print_affected(&mut self, ids: &[EntryId], options: &DisplayOptions) -> Result<usize, DiaryError> {
+        let entries: Vec<Entry> = self.get_entries(0, Some(SortField::Id), false)?.into_iter()
+            .filter(|e| ids.iter().any(|i| i.id == e.id && (i.source.is_none() || i.source == e.source)))
+            .collect();
+        let count = entries.len();
+
+        // every affected entry is printed, so only the fields of `options` are used and not its filters
+        let options = DisplayOptions {
+            hidden: true,
+            only_hidden: false,
+            pinned_only: false,
+            filter_language: None,
+            filter_metadata: Vec::new(),
+            since: None,
+            from: None,
+            to: None,
+            limit: None,
+            offset: None,
+            ..options.clone()
+        };
+        self.print_entries(entries, &options, &[])?;
+        Ok(count)
+    }
+
+    /// Hides or unhides the entries given by `ids` using a single transaction per database.
+    /// The `set` parameter specifies if the entry should be hidden or not. The transactions are
+    /// only committed once every entry was updated, on an error nothing is changed.
+    /// With `dry_run` the entries are only printed using `options`.
+    pub fn hide(&mut self, ids: Vec<EntryId>, set: bool, dry_run: bool, options: &DisplayOptions)
+                -> Result<(), DiaryError> {
+        let mut counter = 0;
+        // grouped by the database they resolve to, `1` and `diary:2` are in the same one without combining
+        let mut ids = self.resolve_ids(&ids)?;
+        ids.sort();
+
+        if dry_run {
+            let count = self.print_affected(&ids, options)?;
+            let action = if set { "hide" } else { "unhide" };
+            println!("Would {} {} {}.", action, color(Cyan).paint(format!("{}", count)),
+                     if count == 1 { "entry" } else { "entries" });
+            return Ok(());
+        }
+
+        let mut transactions = Vec::new();
+        for group in ids.chunk_by(|a, b| a.source == b.source) {
+            // dropping a transaction rolls it back, so returning early discards all changes
+            let tx = self.connection_of(&group[0])?.unchecked_transaction()?;
+
+            let mut previous = Vec::new();
+            {
+                // the statements are prepared once and reused for every id
+                let mut select = tx.prepare("SELECT hidden FROM entries WHERE id = ?1")?;
+                let mut update = tx.prepare("UPDATE entries SET hidden = ?1 WHERE id = ?2")?;
+
+                for i in group {
+                    let hidden: Option<bool> = select.query_row(params![i.id], |row| row.get(0)).optional()?;
+                    if let Some(h) = hidden {
+                        previous.push(Json::Object(vec![("id".into(), Json::Number(i.id)), ("hidden".into(), Json::Bool(h))]));
+                    }
+
+                    counter += update.execute(params![set, i.id])?;
+                }
+            }
+
+            if !previous.is_empty() {
+                Self::log_action(&tx, if set { "hide" } else { "unhide" }, Json::Array(previous))?;
+            }
+            transactions.push(tx);
+        }
+
+        for tx in transactions {
+            tx.commit()?;
+        }
+
+        if counter == 1 {
+            println!("Changed {} entry.", color(Cyan).paint(format!("{}", counter)));
+        } else {
+            println!("Changed {} entries.", color(Cyan).paint(format!("{}", counter)));
+        }
+        Ok(())
+    }
+
+    /// Permanently removes the entries given by `ids` together with their metadata and
+    /// review queue rows using a single transaction per database like `hide`. Returns the
+    /// number of removed entries, with `dry_run` the entries are only printed using `options`
+    /// and the number of them is returned.
+    pub fn delete(&mut self, ids: Vec<EntryId>, dry_run: bool, options: &DisplayOptions)
+                  -> Result<usize, DiaryError> {
+        let mut ids = self.resolve_ids(&ids)?;
+        ids.sort();
+
+        if dry_run {
+            return self.print_affected(&ids, options);
+        }
+
+        let mut counter = 0;
+
+        let mut transactions = Vec::new();
+        for group in ids.chunk_by(|a, b| a.source == b.source) {
+            // dropping a transaction rolls it back, so returning early discards all changes
+            let tx = self.connection_of(&group[0])?.unchecked_transaction()?;
+
+            let mut deleted = Vec::new();
+            for i in group.iter().map(|i| i.id) {
+                let row = tx.query_row(
+                    "SELECT hash, date, keywords, title, content, hidden, language, pinned, attachments, mood FROM entries \
+                     WHERE id = ?1",
+                    params![i], |row| {
+                        let hash: Vec<u8> = row.get(0)?;
+                        let language: Option<String> = row.get(6)?;
+                        let mood: Option<i64> = row.get(9)?;
+                        Ok(vec![
+                            ("id".into(), Json::Number(i)),
+                            ("hash".into(), Json::String(hex::encode(hash))),
+                            ("date".into(), Json::String(row.get(1)?)),
+                            ("keywords".into(), Json::String(row.get(2)?)),
+                            ("title".into(), Json::String(row.get(3)?)),
+                            ("content".into(), Json::String(row.get(4)?)),
+                            ("hidden".into(), Json::Bool(row.get(5)?)),
+                            ("language".into(), language.map_or(Json::Null, Json::String)),
+                            ("pinned".into(), Json::Bool(row.get(7)?)),
+                            ("attachments".into(), Json::String(row.get(8)?)),
+                            ("mood".into(), mood.map_or(Json::Null, Json::Number)),
+                        ])
+                    }).optional()?;
+                let mut row = match row {
+                    Some(r) => r,
+                    None => continue
+                };
+
+                let metadata: Vec<(String, Json)> = {
+                    let mut stmt = tx.prepare("SELECT key, value FROM metadata WHERE entry_id = ?1")?;
+                    let rows = stmt.query_map(params![i], |r| Ok((r.get(0)?, Json::String(r.get(1)?))))?;
+                    rows.collect::<Result<_, rusqlite::Error>>()?
+                };
+                let review: Option<String> = tx.query_row(
+                    "SELECT added FROM review_queue WHERE entry_id = ?1", params![i], |r| r.get(0)).optional()?;
+                row.push(("metadata".into(), Json::Object(metadata)));
+                row.push(("review".into(), review.map_or(Json::Null, Json::String)));
+                deleted.push(Json::Object(row));
+            }
+
+            if !deleted.is_empty() {
+                Self::log_action(&tx, "delete", Json::Array(deleted))?;
+            }
+
+            for i in group.iter().map(|i| i.id) {
+                for sql in ["DELETE FROM metadata WHERE entry_id = ?1", "DELETE FROM review_queue WHERE entry_id = ?1",
+                            "DELETE FROM entries_fts WHERE rowid = ?1"] {
+                    tx.execute(sql, params![i])?;
+                }
+
+                counter += tx.execute("DELETE FROM entries WHERE id = ?1", params![i])?;
+            }
+            transactions.push(tx);
+        }
+
+        for tx in transactions {
+            tx.commit()?;
+        }
+        Ok(counter)
+    }
+
+    /// Records a mutating `action` together with the `data` needed to revert it, only the
+    /// last `UNDO_LIMIT` actions are kept.
+    fn log_action(connection: &Connection, action: &str, data: Json) -> Result<(), DiaryError> {
+        connection.execute(
+            "INSERT INTO action_log (action, time, data) VALUES (?1, ?2, ?3)",
+            params![action, Local::now().to_rfc3339(), data.serialize(false)])?;
+
+        connection.execute(
+            "DELETE FROM action_log WHERE id NOT IN (SELECT id FROM action_log ORDER BY id DESC LIMIT ?1)",
+            params![UNDO_LIMIT])?;
+        Ok(())
+    }
+
+    /// Reverts the most recent `hide`, `unhide` or `delete`, deleted entries are restored with
+    /// their original id. Returns the name of the reverted action and the number of entries,
+    /// `None` if there is nothing to undo.
+    pub fn undo(&mut self) -> Result<Option<(String, usize)>, DiaryError> {
+        let tx = self.connection.transaction()?;
+
+        let last: Option<(i64, String, String)> = tx.query_row(
+            "SELECT id, action, data FROM action_log ORDER BY id DESC LIMIT 1", [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).optional()?;
+        let (log_id, action, data) = match last {
+            Some(l) => l,
+            None => return Ok(None)
+        };
+
+        let corrupt = || DiaryError::Database(String::from("the action log is corrupt"));
+        let records = match json::parse(&data) {
+            Ok(Json::Array(r)) => r,
+            _ => return Err(corrupt())
+        };
+        let string = |r: &Json, key: &str| match r.get(key) {
+            Some(Json::String(s)) => Ok(s.clone()),
+            _ => Err(corrupt())
+        };
+        let number = |r: &Json, key: &str| match r.get(key) {
+            Some(Json::Number(n)) => Ok(*n),
+            _ => Err(corrupt())
+        };
+        let boolean = |r: &Json, key: &str| match r.get(key) {
+            Some(Json::Bool(b)) => Ok(*b),
+            _ => Err(corrupt())
+        };
+
+        for r in &records {
+            let id = number(r, "id")?;
+            match action.as_str() {
+                "hide" | "unhide" => {
+                    tx.execute("UPDATE entries SET hidden = ?1 WHERE id = ?2", params![boolean(r, "hidden")?, id])?;
+                }
+                "delete" => {
+                    let content = string(r, "content")?;
+                    let keywords = string(r, "keywords")?;
+                    let title = string(r, "title")?;
+                    let hash = hex::decode(string(r, "hash")?).map_err(|_| corrupt())?;
+                    let language = match r.get("language") {
+                        Some(Json::String(l)) => Some(l.clone()),
+                        _ => None
+                    };
+                    let pinned = matches!(r.get("pinned"), Some(Json::Bool(true)));
+                    let attachments = match r.get("attachments") {
+                        Some(Json::String(a)) => a.clone(),
+                        _ => String::new()
+                    };
+                    let mood = match r.get("mood") {
+                        Some(Json::Number(m)) => Some(*m),
+                        _ => None
+                    };
+
+                    tx.execute(
+                        r#"
+                        INSERT INTO entries (id, hash, date, keywords, title, content, hidden, language, pinned,
+                                             attachments, mood) VALUES
+                        (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                        "#, params![id, hash, string(r, "date")?, keywords, title, content, boolean(r, "hidden")?,
+                                    language, pinned, attachments, mood])?;
+                    tx.execute(
+                        "INSERT INTO entries_fts (rowid, title, keywords, content) VALUES (?1, ?2, ?3, ?4)",
+                        params![id, title, keywords.replace(';', " "), content])?;
+
+                    if let Some(Json::Object(metadata)) = r.get("metadata") {
+                        for (key, value) in metadata {
+                            if let Json::String(value) = value {
+                                tx.execute("INSERT OR REPLACE INTO metadata (entry_id, key, value) VALUES (?1, ?2, ?3)",
+                                           params![id, key, value])?;
+                            }
+                        }
+                    }
+                    if let Some(Json::String(added)) = r.get("review") {
+                        tx.execute("INSERT OR REPLACE INTO review_queue (entry_id, added) VALUES (?1, ?2)",
+                                   params![id, added])?;
+                    }
+                }
+                _ => return Err(DiaryError::Database(format!("unknown action '{}' in action log", action)))
+            }
+        }
+
+        tx.execute("DELETE FROM action_log WHERE id = ?1", params![log_id])?;
+        tx.commit()?;
+
+        Ok(Some((action, records.len())))
+    }
+
+    /// Prints the raw row of the entry with the given `id` without any formatting.
+    pub fn describe(&mut self, id: &EntryId) -> Result<(), DiaryError> {
+        let connection = self.connection_of(id)?;
+        let row = connection.query_row(
+            "SELECT hash, date, keywords, title, content, hidden FROM entries WHERE id = ?1",
+            params![id.id], |row| {
+                let hash: Vec<u8> = row.get(0)?;
+                let date: String = row.get(1)?;
+                let keywords: String = row.get(2)?;
+                let title: String = row.get(3)?;
+                let content: String = row.get(4)?;
+                let hidden: i64 = row.get(5)?;
+                Ok((hash, date, keywords, title, content, hidden))
+            }).optional()?;
+
+        let text = match row {
+            Some((hash, date, keywords, title, content, hidden)) => {
+                let mut text = format!("id:       {}\nhash:     {}\ndate:     {}\nkeywords: {}\n\
+                                        title:    {}\ncontent:  {} bytes\nhidden:   {}\n", id,
+                                       hex::encode(hash), date, keywords, title, content.len(), hidden);
+                for (key, value) in Self::query_metadata(connection)?.remove(&id.id).unwrap_or_default() {
+                    text += &format!("meta:     {}={}\n", key, value);
+                }
+                text
+            }
+            None => format!("No entry with id {}\n", color(Cyan).paint(format!("{}", id)))
+        };
+
+        self.out.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    /// Retrieves the entries with an id greater than `after_id` ordered by date, hidden
+    /// entries are only included if `hidden` is `true`. A non-empty `ids` restricts the entries to those ids.
+    fn get_entries_to_export(&mut self, hidden: bool, after_id: i64, ids: &[i64]) -> Result<Vec<Entry>, DiaryError> {
+        let mut entries: Vec<Entry> = self.get_entries(after_id, None, false)?.into_iter()
+            .filter(|e| !e.hidden || hidden)
+            .filter(|e| ids.is_empty() || ids.contains(&e.id))
+            .collect();
+        entries.sort_by_key(|e| e.date);
+        Ok(entries)
+    }
+
+    /// Formats an entry as plain text, a date and title header followed by the content
+    fn format_txt(e: &Entry) -> String {
+        format!("{}\n{}\n\n{}\n", e.date.to_rfc2822(), e.title, e.content.trim_end())
+    }
+
+    /// Writes all entries with an id greater than `after_id` as plain text to `writer`,
+    /// ordered by date. Every entry gets a date and title header followed by its content,
+    /// entries are separated by a divider. Hidden entries will get written if `hidden` is `true`.
+    /// Returns the number of written entries.
+    pub fn export_txt(&mut self, mut writer: impl Write, hidden: bool, after_id: i64,
+                      ids: &[i64]) -> Result<usize, DiaryError> {
+        let entries = self.get_entries_to_export(hidden, after_id, ids)?;
+        let mut progress = Progress::new("Exporting", entries.len());
+
+        for e in &entries {
+            let text = format!("{:=<1$}\n{2}\n", "", 80, Self::format_txt(e));
+            writer.write_all(text.as_bytes())?;
+            progress.inc();
+        }
+
+        if !entries.is_empty() {
+            writeln!(writer, "{:=<1$}", "", 80)?;
+        }
+
+        Ok(entries.len())
+    }
+
+    /// Writes all entries with an id greater than `after_id` as Markdown to `writer`, ordered by
+    /// date. Every entry becomes a section headed by its title with the date below, the keywords
+    /// as `#hashtags` and the content. Hidden entries will get written if `hidden` is `true`.
+    /// Returns the number of written entries.
+    pub fn export_markdown(&mut self, mut writer: impl Write, hidden: bool, after_id: i64,
+                           ids: &[i64]) -> Result<usize, DiaryError> {
+        let entries = self.get_entries_to_export(hidden, after_id, ids)?;
+        let mut progress = Progress::new("Exporting", entries.len());
+
+        for (i, e) in entries.iter().enumerate() {
+            let mut text = format!("{}## {}\n\n*{}*\n\n", if i > 0 { "\n" } else { "" }, e.title, e.date.to_rfc2822());
+
+            let tags: Vec<String> = e.keywords.iter()
+                .filter(|k| !k.is_empty())
+                .map(|k| format!("#{}", k))
+                .collect();
+            if !tags.is_empty() {
+                text.push_str(&format!("{}\n\n", tags.join(" ")));
+            }
+            text.push_str(&format!("{}\n", e.content.trim_end()));
+
+            writer.write_all(text.as_bytes())?;
+            progress.inc();
+        }
+
+        Ok(entries.len())
+    }
+
+    /// Writes all entries with an id greater than `after_id` as CSV to `writer`, ordered by date.
+    /// A header row is followed by a row per entry, keywords are joined by `;`.
+    /// Hidden entries will get written if `hidden` is `true`. Returns the number of written entries.
+    pub fn export_csv(&mut self, mut writer: impl Write, hidden: bool, after_id: i64,
+                      ids: &[i64]) -> Result<usize, DiaryError> {
+        let entries = self.get_entries_to_export(hidden, after_id, ids)?;
+        let mut progress = Progress::new("Exporting", entries.len());
+
+        write!(writer, "id,date,keywords,title,content,hidden\r\n")?;
+
+        for e in &entries {
+            let row = [e.id.to_string(), e.date.to_rfc3339(), Self::join_keywords(&e.keywords), e.title.clone(),
+                e.content.clone(), e.hidden.to_string()];
+            let row: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+
+            write!(writer, "{}\r\n", row.join(","))?;
+            progress.inc();
+        }
+
+        Ok(entries.len())
+    }
+
+    /// Converts an entry into a JSON object, the date is written as RFC 3339 and the hash in hex
+    fn to_json(e: &Entry) -> Json {
+        Json::Object(vec![
+            ("id".into(), Json::Number(e.id)),
+            ("hash".into(), Json::String(hex::encode(&e.hash))),
+            ("date".into(), Json::String(e.date.to_rfc3339())),
+            ("keywords".into(), Json::Array(e.keywords.iter()
+                .filter(|k| !k.is_empty())
+                .map(|k| Json::String(k.clone()))
+                .collect())),
+            ("title".into(), Json::String(e.title.clone())),
+            ("content".into(), Json::String(e.content.clone())),
+            ("hidden".into(), Json::Bool(e.hidden)),
+            ("language".into(), e.language.clone().map_or(Json::Null, Json::String)),
+            ("metadata".into(), Json::Object(e.metadata.iter()
+                .map(|(k, v)| (k.clone(), Json::String(v.clone())))
+                .collect())),
+            ("attachments".into(), Json::Array(e.attachments.iter().map(|a| Json::String(a.clone())).collect())),
+            ("mood".into(), e.mood.map_or(Json::Null, |m| Json::Number(m as i64))),
+        ])
+    }
+
+    /// Writes all entries with an id greater than `after_id` as a JSON array to `writer`,
+    /// ordered by date. With `pretty` the output is indented for reading by hand.
+    /// Hidden entries will get written if `hidden` is `true`. Returns the number of written entries.
+    pub fn export_json(&mut self, mut writer: impl Write, hidden: bool, after_id: i64, ids: &[i64],
+                       pretty: bool) -> Result<usize, DiaryError> {
+        let entries = self.get_entries_to_export(hidden, after_id, ids)?;
+        let mut progress = Progress::new("Exporting", entries.len());
+
+        let values = entries.iter().map(|e| {
+            progress.inc();
+            Self::to_json(e)
+        }).collect();
+
+        writeln!(writer, "{}", Json::Array(values).serialize(pretty))?;
+
+        Ok(entries.len())
+    }
+
+    /// Converts a record of an import into an entry, the `id` and `hash` of the record are ignored.
+    fn from_json(value: &Json) -> Result<Entry, String> {
+        let string = |key: &str| match value.get(key) {
+            Some(Json::String(s)) => Ok(s.clone()),
+            Some(_) => Err(format!("`{}` has to be a string", key)),
+            None => Err(format!("`{}` is missing", key))
+        };
+
+        if !matches!(value, Json::Object(_)) {
+            return Err(String::from("record has to be an object"));
+        }
+
+        let date = string("date")?;
+        let date = DateTime::parse_from_rfc3339(&date)
+            .map_err(|_| format!("invalid date '{}'", date))?
+            .with_timezone(&Local);
+
+        let keywords = match value.get("keywords") {
+            Some(Json::Array(k)) => k.iter().map(|k| match k {
+                Json::String(k) => Ok(k.trim().to_lowercase()),
+                _ => Err(String::from("`keywords` has to contain strings"))
+            }).filter(|k| k.as_ref().map_or(true, |k| !k.is_empty())).collect::<Result<Vec<String>, String>>()?,
+            None => Vec::new(),
+            Some(_) => return Err(String::from("`keywords` has to be an array"))
+        };
+
+        let hidden = match value.get("hidden") {
+            Some(Json::Bool(b)) => *b,
+            None => false,
+            Some(_) => return Err(String::from("`hidden` has to be a boolean"))
+        };
+
+        let language = match value.get("language") {
+            Some(Json::String(l)) => Some(l.to_lowercase()),
+            Some(Json::Null) | None => None,
+            Some(_) => return Err(String::from("`language` has to be a string"))
+        };
+
+        let metadata = match value.get("metadata") {
+            Some(Json::Object(m)) => m.iter().map(|(k, v)| match v {
+                Json::String(v) => Ok((k.clone(), v.clone())),
+                _ => Err(String::from("`metadata` has to contain strings"))
+            }).collect::<Result<Vec<(String, String)>, String>>()?,
+            None => Vec::new(),
+            Some(_) => return Err(String::from("`metadata` has to be an object"))
+        };
+
+        let (title, removed_title) = sanitize_text(&string("title")?);
+        let (content, removed_content) = sanitize_text(&string("content")?);
+        if removed_title + removed_content > 0 {
+            return Err(String::from("title or content contain control characters"));
+        }
+
+        let attachments = match value.get("attachments") {
+            Some(Json::Array(a)) => a.iter().map(|a| match a {
+                Json::String(a) if !a.contains(';') => Ok(a.clone()),
+                _ => Err(String::from("`attachments` has to contain paths without ';'"))
+            }).collect::<Result<Vec<String>, String>>()?,
+            None => Vec::new(),
+            Some(_) => return Err(String::from("`attachments` has to be an array"))
+        };
+
+        let mood = match value.get("mood") {
+            Some(Json::Number(m)) if (1..=5).contains(m) => Some(*m as u8),
+            None | Some(Json::Null) => None,
+            Some(_) => return Err(String::from("`mood` has to be a number from 1 to 5"))
+        };
+
+        Ok(Entry { id: 0, hash: Vec::new(), date, keywords, title, content, hidden, pinned: false, language,
+                   metadata, attachments, mood, source: None })
+    }
+
+    /// Imports a JSON array of entries in the shape written by `export_json`. Every record gets
+    /// a new id and a freshly computed hash, the date and hidden flag are kept. Malformed records
+    /// are rejected and records whose hash already exists are skipped, both with a warning.
+    /// With `dry_run` nothing is written, the entries which would be imported are printed and the
+    /// summary tells what would have happened.
+    pub fn import_json(&mut self, mut reader: impl Read, dry_run: bool) -> Result<ImportSummary, DiaryError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+
+        let records = match json::parse(&text) {
+            Ok(Json::Array(records)) => records,
+            Ok(_) => return Err(DiaryError::Invalid(String::from("the import has to be a JSON array of entries"))),
+            Err(e) => return Err(DiaryError::Invalid(format!("the import isn't valid JSON: {}", e)))
+        };
+
+        let mut hashes: HashSet<Vec<u8>> = {
+            let mut stmt = self.connection.prepare("SELECT hash FROM entries")?;
+            let hashes = stmt.query_map([], |row| row.get(0))?;
+            hashes.collect::<Result<_, rusqlite::Error>>()?
+        };
+
+        let mut summary = ImportSummary::default();
+        let mut to_import = Vec::new();
+
+        for (n, record) in records.iter().enumerate() {
+            let entry = match Self::from_json(record).and_then(|e| {
+                self.check_content_length(&e.content).map(|_| e)
+            }) {
+                Ok(e) => e,
+                Err(e) => {
+                    eprintln!("{} rejected record {}: {}", color(Yellow).paint("Warning:"), n + 1, e);
+                    summary.rejected += 1;
+                    continue;
+                }
+            };
+
+            if !hashes.insert(self.hash_of(&entry)) {
+                eprintln!("{} skipped record {}: '{}' already exists", color(Yellow).paint("Warning:"),
+                          n + 1, entry.title);
+                summary.skipped += 1;
+                continue;
+            }
+
+            to_import.push(entry);
+            summary.imported += 1;
+        }
+
+        if dry_run {
+            let options = DisplayOptions { date: true, keywords: true, hidden: true, ..Default::default() };
+            self.print_entries(to_import, &options, &[])?;
+            return Ok(summary);
+        }
+
+        let rows: Vec<(Entry, Vec<u8>)> = to_import.into_iter()
+            .map(|e| {
+                let hash = self.hash_of(&e);
+                (e, hash)
+            })
+            .collect();
+
+        // rolled back when dropped, so a failure in the middle doesn't leave a partial import
+        let tx = self.connection.transaction()?;
+        let mut progress = Progress::new("Importing", rows.len());
+        for (entry, hash) in &rows {
+            progress.inc();
+            Self::insert_row(&tx, entry, hash)?;
+        }
+        tx.commit()?;
+
+        Ok(summary)
+    }
+
+    /// Writes every entry with an id greater than `after_id` as plain text into its own file
+    /// inside `dir`, optionally split into subdirectories by year or month of the entry date.
+    /// Hidden entries will get written if `hidden` is `true`.
+    /// Returns the number of written entries.
+    pub fn export_txt_dir(&mut self, dir: &Path, split_by: Option<SplitBy>, hidden: bool,
+                          after_id: i64, ids: &[i64]) -> Result<usize, DiaryError> {
+        let entries = self.get_entries_to_export(hidden, after_id, ids)?;
+        let mut progress = Progress::new("Exporting", entries.len());
+
+        for e in &entries {
+            let mut path = SplitBy::directory(split_by, dir, &e.date);
+            create_dir_all(&path).map_err(|e| {
+                DiaryError::Invalid(format!("couldn't create directory '{}': {}", path.display(), e))
+            })?;
+
+            path.push(format!("{}-{}.txt", e.id, slugify(&e.title)));
+            File::create(&path)
+                .and_then(|mut f| f.write_all(Self::format_txt(e).as_bytes()))
+                .map_err(|e| DiaryError::Invalid(format!("couldn't write file '{}': {}", path.display(), e)))?;
+            progress.inc();
+        }
+
+        Ok(entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: i64, title: &str, keywords: &[&str], content: &str) -> Entry {
+        Entry {
+            id,
+            hash: Vec::new(),
+            date: Local::now(),
+            keywords: keywords.iter().map(|k| k.to_string()).collect(),
+            title: title.to_string(),
+            content: content.to_string(),
+            hidden: false,
+            pinned: false,
+            language: None,
+            metadata: Vec::new(),
+            attachments: Vec::new(),
+            mood: None,
+            source: None,
+        }
+    }
+
+    /// Writes `e` like a listing does, without colors
+    fn write(e: &Entry, options: &DisplayOptions) -> String {
+        crate::color::set_enabled(false);
+        let mut out: Vec<u8> = Vec::new();
+        Diary::write_entry(&mut out, 80, e, options, &[], &Local::now()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn write_entry_pads_title_by_its_display_width() {
+        let options = DisplayOptions { date: true, ..Default::default() };
+
+        for title in ["Café visit", "日記"] {
+            let e = entry(1, title, &[], "");
+            let output = write(&e, &options);
+            let line = output.lines().find(|l| l.starts_with(title)).unwrap();
+            let date = line.find(&options.format_date(&e.date)).unwrap();
+            assert_eq!(line[..date].width(), 40);
+        }
+    }
+
+    #[test]
+    fn write_entry_without_keywords() {
+        let options = DisplayOptions { keywords: true, ..Default::default() };
+
+        assert!(write(&entry(1, "Empty", &[], ""), &options).contains("Keywords: (none)\n"));
+        assert!(write(&entry(1, "Blank", &["", " "], ""), &options).contains("Keywords: (none)\n"));
+        assert!(write(&entry(1, "Work", &["", "work"], ""), &options).contains("Keywords: work\n"));
+    }
+
+    #[test]
+    fn matching_finds_an_entry_once() {
+        let entries = vec![entry(1, "Road trip", &["berlin", "travel"], ""), entry(2, "Work", &["office"], "")];
+        let searchfor = vec![String::from("trip"), String::from("berlin")];
+
+        let found = Diary::matching(entries, &searchfor, false, false, false);
+        assert_eq!(found.iter().map(|e| e.id).collect::<Vec<i64>>(), vec![1]);
+    }
+
+    #[test]
+    fn add_rejects_blank_title() {
+        let mut diary = Diary::open_in_memory();
+
+        for title in ["", "   ", "\t\n"] {
+            let result = diary.add(Vec::new(), title.to_string(), String::from("content"), None, Vec::new(),
+                                   Vec::new(), None);
+            assert!(matches!(result, Err(DiaryError::Invalid(_))), "{:?}", title);
+        }
+        assert_eq!(diary.count(true).unwrap(), 0);
+    }
+
+    #[test]
+    fn hide_changes_nothing_if_an_entry_fails() {
+        let mut diary = Diary::open_in_memory();
+        for title in ["First", "Second"] {
+            diary.add(Vec::new(), title.to_string(), String::from("content"), None, Vec::new(), Vec::new(), None)
+                .unwrap();
+        }
+        diary.connection.execute_batch(
+            "CREATE TRIGGER fail BEFORE UPDATE ON entries WHEN NEW.id = 2 BEGIN SELECT RAISE(ABORT, 'fail'); END;")
+            .unwrap();
+
+        let ids = vec![EntryId { source: None, id: 1 }, EntryId { source: None, id: 2 }];
+        assert!(diary.hide(ids, true, false, &DisplayOptions::default()).is_err());
+        assert!(!diary.get_entry(1).unwrap().unwrap().hidden);
+        assert_eq!(diary.connection.query_row("SELECT COUNT(*) FROM action_log", [], |row| row.get::<_, i64>(0))
+                       .unwrap(), 0);
+    }
+
+    #[test]
+    fn delete_checks_every_database_before_removing_anything() {
+        let mut diary = Diary::open_in_memory();
+        for title in ["First", "Second"] {
+            diary.add(Vec::new(), title.to_string(), String::from("content"), None, Vec::new(), Vec::new(), None)
+                .unwrap();
+        }
+
+        let ids = vec![EntryId { source: None, id: 1 }, EntryId { source: Some(String::from("other")), id: 2 }];
+        assert!(diary.delete(ids, false, &DisplayOptions::default()).is_err());
+        assert!(diary.get_entry(1).unwrap().is_some());
+
+        let ids = vec![EntryId { source: None, id: 2 }, EntryId { source: None, id: 1 }];
+        assert_eq!(diary.delete(ids, false, &DisplayOptions::default()).unwrap(), 2);
+        assert!(diary.get_entry(1).unwrap().is_none());
+    }
+
+    #[test]
+    fn split_keywords_without_empty_keywords() {
+        assert_eq!(Diary::split_keywords(""), Vec::<String>::new());
+        assert_eq!(Diary::split_keywords("a"), vec![String::from("a")]);
+        assert_eq!(Diary::split_keywords(";a;;b;"), vec![String::from("a"), String::from("b")]);
+    }
+
+    #[test]
+    fn migrate_keywords_recomputes_matching_hashes() {
+        crate::progress::set_enabled(false);
+        let mut diary = Diary::open_in_memory();
+        let date = Local::now().to_rfc3339();
+        for (id, keywords, tampered) in [(1, ";k", false), (2, "a;;b", false), (3, "x;", true)] {
+            let hash = diary.hash_algorithm.digest(&[keywords, "Title", if tampered { "other" } else { "content" },
+                                                     &date]);
+            diary.connection.execute(
+                "INSERT INTO entries (id, hash, date, keywords, title, content, hidden) VALUES \
+                 (?1, ?2, ?3, ?4, 'Title', 'content', 0)", params![id, hash, date, keywords]).unwrap();
+        }
+
+        Diary::migrate_keywords(&diary.connection).unwrap();
+
+        let keywords: Vec<String> = (1..=3).map(|id| diary.get_entry(id).unwrap().unwrap().keywords.join(";")).collect();
+        assert_eq!(keywords, vec!["k", "a;b", "x"]);
+        // only the tampered entry still fails verification
+        assert_eq!(diary.hash_mismatches().unwrap().iter().map(|m| m.id).collect::<Vec<i64>>(), vec![3]);
+    }
+
+    #[test]
+    fn undo_rejects_a_corrupt_action_log() {
+        let mut diary = Diary::open_in_memory();
+        diary.connection.execute(
+            "INSERT INTO action_log (action, time, data) VALUES ('hide', '', '[{\"id\": 1}]')", []).unwrap();
+
+        assert!(matches!(diary.undo(), Err(DiaryError::Database(_))));
+        // the failed undo is rolled back, so the action stays logged
+        assert_eq!(diary.connection.query_row("SELECT COUNT(*) FROM action_log", [], |row| row.get::<_, i64>(0))
+                       .unwrap(), 1);
+    }
+
+    #[test]
+    fn condition_matches_shows() {
+        let mut diary = Diary::open_in_memory();
+        for (title, language, metadata) in [("First", Some("en"), vec![(String::from("place"), String::from("home"))]),
+                                            ("Second", None, Vec::new()), ("Third", Some("de"), Vec::new())] {
+            diary.add(Vec::new(), title.to_string(), String::from("content"), language, metadata, Vec::new(), None)
+                .unwrap();
+        }
+        diary.connection.execute("UPDATE entries SET hidden = 1 WHERE id = 2", []).unwrap();
+        diary.connection.execute("UPDATE entries SET pinned = 1 WHERE id = 3", []).unwrap();
+        let yesterday = Local::now() - Duration::days(1);
+        diary.connection.execute("UPDATE entries SET date = ?1 WHERE id = 1", params![yesterday]).unwrap();
+
+        let all = diary.get_entries(0, None, false).unwrap();
+        let filters = [
+            DisplayOptions::default(),
+            DisplayOptions { hidden: true, ..Default::default() },
+            DisplayOptions { only_hidden: true, ..Default::default() },
+            DisplayOptions { pinned_only: true, ..Default::default() },
+            DisplayOptions { filter_language: Some(String::from("en")), ..Default::default() },
+            DisplayOptions { filter_metadata: vec![(String::from("place"), String::from("home"))], ..Default::default() },
+            DisplayOptions { since: Some(yesterday), hidden: true, ..Default::default() },
+            DisplayOptions { from: Some(yesterday), to: Some(yesterday + Duration::hours(1)), ..Default::default() },
+        ];
+
+        for options in &filters {
+            let (condition, values) = options.condition(0);
+            let values: Vec<&dyn ToSql> = values.iter().map(|v| v.as_ref()).collect();
+            let found: Vec<i64> = Diary::query_entries_where(&diary.connection, None, &condition, &values, "ORDER BY id")
+                .unwrap().iter().map(|e| e.id).collect();
+            let mut shown: Vec<i64> = all.iter().filter(|e| options.shows(e)).map(|e| e.id).collect();
+            shown.sort();
+
+            assert_eq!(found, shown, "{:?}", options);
+            assert_eq!(diary.count_where(&condition, &values).unwrap(), shown.len());
+        }
+    }
+
+    #[test]
+    fn parse_timezone_by_name_follows_daylight_saving_time() {
+        let tz = parse_timezone("Europe/Vienna").unwrap();
+        let winter = Local.from_utc_datetime(&NaiveDate::from_ymd_opt(2024, 1, 15).unwrap().and_hms(12, 0, 0));
+        let summer = Local.from_utc_datetime(&NaiveDate::from_ymd_opt(2024, 7, 15).unwrap().and_hms(12, 0, 0));
+
+        assert_eq!(tz.format(&winter), "Mon, 15 Jan 2024 13:00:00 +0100");
+        assert_eq!(tz.format(&summer), "Mon, 15 Jul 2024 14:00:00 +0200");
+        assert_eq!(parse_timezone("+02:00").unwrap(), Timezone::Fixed(FixedOffset::east(7200)));
+        assert!(parse_timezone("Europe/Nowhere").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_writable_rejects_read_only_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("didi-read-only-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("digital_diary.sqlite");
+        assert!(Diary::check_writable(&path).is_ok());
+
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+        // root can write into read-only directories, so there is nothing to check then
+        let writable = File::create(dir.join("probe")).is_ok();
+        let result = Diary::check_writable(&path);
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        if !writable {
+            assert!(matches!(result, Err(DiaryError::Database(_))));
         }
     }
 }
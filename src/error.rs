@@ -0,0 +1,50 @@
+use std::fmt;
+
+/// Errors returned by the public methods of `Diary`, `main` prints them and exits with status 1
+#[derive(Debug)]
+pub enum DiaryError {
+    /// The database file is missing or can't be used, the message explains how to fix it
+    Database(String),
+    /// A statement failed inside of SQLite
+    Sql(rusqlite::Error),
+    /// The input given by the user isn't valid
+    Invalid(String),
+    /// Reading or writing a file or the output failed
+    Io(std::io::Error),
+}
+
+impl fmt::Display for DiaryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiaryError::Database(m) => write!(f, "{}", m),
+            DiaryError::Sql(e) if e.sqlite_error_code() == Some(rusqlite::ErrorCode::ReadOnly) => {
+                write!(f, "the database is read-only, check the permissions of its file")
+            }
+            DiaryError::Sql(e) => write!(f, "database error: {}", e),
+            DiaryError::Invalid(m) => write!(f, "{}", m),
+            DiaryError::Io(e) => write!(f, "couldn't read or write: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DiaryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DiaryError::Sql(e) => Some(e),
+            DiaryError::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<rusqlite::Error> for DiaryError {
+    fn from(e: rusqlite::Error) -> Self {
+        DiaryError::Sql(e)
+    }
+}
+
+impl From<std::io::Error> for DiaryError {
+    fn from(e: std::io::Error) -> Self {
+        DiaryError::Io(e)
+    }
+}
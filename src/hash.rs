@@ -0,0 +1,65 @@
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+/// Algorithm used for the integrity hash of entries, it is chosen when the
+/// database is created and recorded inside of it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    pub const NAMES: [&'static str; 4] = ["sha256", "sha384", "sha512", "blake3"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sha256" => Some(HashAlgorithm::Sha256),
+            "sha384" => Some(HashAlgorithm::Sha384),
+            "sha512" => Some(HashAlgorithm::Sha512),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha384 => "sha384",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Hashes all `parts` one after another
+    pub fn digest(&self, parts: &[&str]) -> Vec<u8> {
+        fn run<D: Digest>(parts: &[&str]) -> Vec<u8> {
+            let mut hasher = D::new();
+            for p in parts {
+                hasher.update(p);
+            }
+            hasher.finalize().to_vec()
+        }
+
+        match self {
+            HashAlgorithm::Sha256 => run::<Sha256>(parts),
+            HashAlgorithm::Sha384 => run::<Sha384>(parts),
+            HashAlgorithm::Sha512 => run::<Sha512>(parts),
+            HashAlgorithm::Blake3 => run::<blake3::Hasher>(parts),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blake3_digest_of_parts() {
+        assert_eq!(hex::encode(HashAlgorithm::Blake3.digest(&["a", "bc"])),
+                   "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85");
+        assert_eq!(HashAlgorithm::from_name("blake3"), Some(HashAlgorithm::Blake3));
+    }
+}
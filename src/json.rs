@@ -0,0 +1,263 @@
+use std::fmt::Write;
+
+/// A JSON value, objects keep the order of their members
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Looks up the member `key` of an object
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    /// Serializes the value, `pretty` puts every member on its own line indented by two spaces
+    pub fn serialize(&self, pretty: bool) -> String {
+        let mut out = String::new();
+        self.write(&mut out, pretty, 0);
+        out
+    }
+
+    fn write(&self, out: &mut String, pretty: bool, indent: usize) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Json::Number(n) => out.push_str(&n.to_string()),
+            Json::String(s) => write_string(out, s),
+            Json::Array(values) => {
+                if values.is_empty() {
+                    out.push_str("[]");
+                    return;
+                }
+
+                out.push('[');
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, pretty, indent + 1);
+                    v.write(out, pretty, indent + 1);
+                }
+                newline(out, pretty, indent);
+                out.push(']');
+            }
+            Json::Object(members) => {
+                if members.is_empty() {
+                    out.push_str("{}");
+                    return;
+                }
+
+                out.push('{');
+                for (i, (k, v)) in members.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, pretty, indent + 1);
+                    write_string(out, k);
+                    out.push_str(if pretty { ": " } else { ":" });
+                    v.write(out, pretty, indent + 1);
+                }
+                newline(out, pretty, indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+/// Starts a new line with the given indentation level, does nothing for compact output
+fn newline(out: &mut String, pretty: bool, indent: usize) {
+    if pretty {
+        out.push('\n');
+        out.push_str(&"  ".repeat(indent));
+    }
+}
+
+/// Writes `s` as a quoted JSON string
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Parses a JSON document, numbers have to be integers
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser { chars: text.char_indices().peekable(), text };
+    let value = parser.value()?;
+    parser.whitespace();
+
+    match parser.chars.next() {
+        Some((i, _)) => Err(format!("unexpected character at position {}", i)),
+        None => Ok(value)
+    }
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    text: &'a str,
+}
+
+impl Parser<'_> {
+    fn whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((i, c)) => Err(format!("expected '{}' but found '{}' at position {}", expected, c, i)),
+            None => Err(format!("expected '{}' but the input ended", expected))
+        }
+    }
+
+    /// Consumes `word` which starts with the already peeked character
+    fn literal(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        for c in word.chars() {
+            self.expect(c)?;
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.whitespace();
+
+        match self.chars.peek().copied() {
+            Some((_, 'n')) => self.literal("null", Json::Null),
+            Some((_, 't')) => self.literal("true", Json::Bool(true)),
+            Some((_, 'f')) => self.literal("false", Json::Bool(false)),
+            Some((_, '"')) => self.string().map(Json::String),
+            Some((_, '[')) => self.array(),
+            Some((_, '{')) => self.object(),
+            Some((i, c)) if c == '-' || c.is_ascii_digit() => self.number(i),
+            Some((i, c)) => Err(format!("unexpected character '{}' at position {}", c, i)),
+            None => Err(String::from("unexpected end of input"))
+        }
+    }
+
+    fn number(&mut self, start: usize) -> Result<Json, String> {
+        let mut end = start;
+        while let Some((i, c)) = self.chars.next_if(|(_, c)| *c == '-' || c.is_ascii_alphanumeric() || *c == '.' || *c == '+') {
+            end = i + c.len_utf8();
+        }
+
+        let number = &self.text[start..end];
+        number.parse::<i64>()
+            .map(Json::Number)
+            .map_err(|_| format!("invalid or non-integer number '{}' at position {}", number, start))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(s),
+                Some((i, '\\')) => match self.chars.next() {
+                    Some((_, '"')) => s.push('"'),
+                    Some((_, '\\')) => s.push('\\'),
+                    Some((_, '/')) => s.push('/'),
+                    Some((_, 'b')) => s.push('\u{8}'),
+                    Some((_, 'f')) => s.push('\u{c}'),
+                    Some((_, 'n')) => s.push('\n'),
+                    Some((_, 'r')) => s.push('\r'),
+                    Some((_, 't')) => s.push('\t'),
+                    Some((_, 'u')) => {
+                        let mut code = self.hex_escape(i)?;
+                        // characters outside the basic plane are written as surrogate pairs
+                        if (0xD800..0xDC00).contains(&code) {
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.hex_escape(i)?;
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                        }
+                        match char::from_u32(code) {
+                            Some(c) => s.push(c),
+                            None => return Err(format!("invalid unicode escape at position {}", i))
+                        }
+                    }
+                    _ => return Err(format!("invalid escape sequence at position {}", i))
+                },
+                Some((_, c)) => s.push(c),
+                None => return Err(String::from("unterminated string"))
+            }
+        }
+    }
+
+    fn hex_escape(&mut self, position: usize) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            match self.chars.next().and_then(|(_, c)| c.to_digit(16)) {
+                Some(d) => code = code * 16 + d,
+                None => return Err(format!("invalid unicode escape at position {}", position))
+            }
+        }
+        Ok(code)
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+
+        self.whitespace();
+        if self.chars.next_if(|(_, c)| *c == ']').is_some() {
+            return Ok(Json::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.whitespace();
+
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(Json::Array(values)),
+                Some((i, c)) => return Err(format!("expected ',' or ']' but found '{}' at position {}", c, i)),
+                None => return Err(String::from("unterminated array"))
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+
+        self.whitespace();
+        if self.chars.next_if(|(_, c)| *c == '}').is_some() {
+            return Ok(Json::Object(members));
+        }
+
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.whitespace();
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.whitespace();
+
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(Json::Object(members)),
+                Some((i, c)) => return Err(format!("expected ',' or '}}' but found '{}' at position {}", c, i)),
+                None => return Err(String::from("unterminated object"))
+            }
+        }
+    }
+}
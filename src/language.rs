@@ -0,0 +1,175 @@
+/// Common words of every supported language, identified by their ISO 639-1 code
+const STOP_WORDS: [(&str, &[&str]); 7] = [
+    ("en", &["the", "and", "is", "was", "to", "of", "in", "it", "that", "i", "my", "with",
+        "for", "on", "this", "but", "have", "had", "not", "are", "we", "you", "be", "at"]),
+    ("de", &["der", "die", "das", "und", "ist", "war", "ich", "nicht", "mit", "ein", "eine",
+        "zu", "auf", "mein", "heute", "sich", "den", "dem", "auch", "wir", "es", "hat", "habe"]),
+    ("fr", &["le", "la", "les", "et", "est", "je", "une", "un", "des", "du", "pas", "que",
+        "mon", "ma", "avec", "pour", "dans", "sur", "nous", "il", "elle", "ai", "au", "ce"]),
+    ("es", &["el", "la", "los", "las", "y", "es", "yo", "una", "un", "de", "que", "no",
+        "mi", "con", "para", "por", "en", "hoy", "pero", "muy", "fue", "estoy", "del", "se"]),
+    ("it", &["il", "lo", "gli", "e", "è", "io", "una", "un", "di", "che", "non", "mio",
+        "con", "per", "sono", "oggi", "ma", "molto", "della", "ho", "del", "nel", "si", "anche"]),
+    ("nl", &["de", "het", "een", "en", "is", "ik", "niet", "van", "met", "op", "mijn",
+        "voor", "dat", "vandaag", "maar", "zijn", "er", "ook", "wij", "heb", "was", "naar", "te"]),
+    ("pt", &["o", "os", "as", "e", "é", "eu", "uma", "um", "de", "que", "não", "meu",
+        "com", "para", "por", "em", "hoje", "mas", "muito", "foi", "estou", "do", "da", "se"]),
+];
+
+/// Detects the language of `text` by counting common words of each supported language.
+/// Returns the ISO 639-1 code or `None` if the detection is uncertain.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = text.split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+
+    let mut scores: Vec<(&str, usize)> = STOP_WORDS.iter()
+        .map(|(code, stop_words)| {
+            (*code, words.iter().filter(|w| stop_words.contains(&w.as_str())).count())
+        })
+        .collect();
+    scores.sort_by_key(|s| std::cmp::Reverse(s.1));
+
+    let (code, best) = scores[0];
+    let second = scores[1].1;
+
+    // require a few hits and a clear lead over the runner-up
+    if best >= 3 && best * 2 > second * 3 {
+        Some(code)
+    } else {
+        None
+    }
+}
+
+/// Whether `word`, which has to be lowercase, is a common word in any supported language
+pub fn is_stop_word(word: &str) -> bool {
+    STOP_WORDS.iter().any(|(_, stop_words)| stop_words.contains(&word))
+}
+
+/// Returns up to `n` of the most frequent words in `text` which aren't common words,
+/// words shorter than three characters are ignored.
+pub fn salient_words(text: &str, n: usize) -> Vec<String> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+
+    for word in text.split(|c: char| !c.is_alphabetic()) {
+        let word = word.to_lowercase();
+        if word.chars().count() < 3 || is_stop_word(&word) {
+            continue;
+        }
+
+        match counts.iter_mut().find(|(w, _)| *w == word) {
+            Some((_, c)) => *c += 1,
+            None => counts.push((word, 1))
+        }
+    }
+
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.into_iter().take(n).map(|(w, _)| w).collect()
+}
+
+/// Returns the significant words of `title` in order of appearance, lowercased and
+/// without duplicates. Common words and words shorter than three characters are ignored.
+pub fn title_keywords(title: &str) -> Vec<String> {
+    let mut keywords: Vec<String> = Vec::new();
+
+    for word in title.split(|c: char| !c.is_alphanumeric()) {
+        let word = word.to_lowercase();
+        if word.chars().count() >= 3 && !is_stop_word(&word) && !keywords.contains(&word) {
+            keywords.push(word);
+        }
+    }
+
+    keywords
+}
+
+/// Scores how well the characters of `pattern` appear in order within `text`, other characters
+/// may come in between. Consecutive characters and characters at the start of words score
+/// higher, gaps cost points. Returns `None` if `text` doesn't contain all characters of `pattern`.
+pub fn fuzzy_score(pattern: &str, text: &str) -> Option<i64> {
+    let pattern: Vec<char> = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+    let text: Vec<char> = text.chars().collect();
+    if pattern.is_empty() {
+        return None;
+    }
+
+    // the end of the first occurrence, then back to the latest start to get the tightest window
+    let mut p = 0;
+    let end = text.iter().position(|c| {
+        if *c == pattern[p] {
+            p += 1;
+        }
+        p == pattern.len()
+    })?;
+    let mut p = pattern.len();
+    let start = (0..=end).rev().find(|&i| {
+        if text[i] == pattern[p - 1] {
+            p -= 1;
+        }
+        p == 0
+    })?;
+
+    let mut score = 0;
+    let mut p = 0;
+    let mut last: Option<usize> = None;
+    for i in start..=end {
+        if p == pattern.len() || text[i] != pattern[p] {
+            continue;
+        }
+
+        score += 16;
+        if i == 0 || !text[i - 1].is_alphanumeric() {
+            score += 8;
+        }
+        match last {
+            Some(l) if l + 1 == i => score += 8,
+            Some(l) => score -= 3 + (i - l - 2) as i64,
+            None => {}
+        }
+        last = Some(i);
+        p += 1;
+    }
+
+    Some(score)
+}
+
+/// Lowercases `text` and replaces accented Latin letters with their base letters,
+/// e.g. `Café` becomes `cafe`. Used to compare words regardless of case and accents.
+pub fn normalize(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+
+    for c in text.chars().flat_map(char::to_lowercase) {
+        let base = match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+            'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+            'ď' | 'đ' | 'ð' => "d",
+            'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+            'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+            'ĥ' | 'ħ' => "h",
+            'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+            'ĵ' => "j",
+            'ķ' => "k",
+            'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+            'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+            'ŕ' | 'ŗ' | 'ř' => "r",
+            'ś' | 'ŝ' | 'ş' | 'š' | 'ș' => "s",
+            'ţ' | 'ť' | 'ŧ' | 'ț' => "t",
+            'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+            'ŵ' => "w",
+            'ý' | 'ÿ' | 'ŷ' => "y",
+            'ź' | 'ż' | 'ž' => "z",
+            'ß' => "ss",
+            'æ' => "ae",
+            'œ' => "oe",
+            'þ' => "th",
+            c => {
+                normalized.push(c);
+                continue;
+            }
+        };
+        normalized.push_str(base);
+    }
+
+    normalized
+}
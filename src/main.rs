@@ -1,20 +1,319 @@
-use std::io::{stdin, stdout, Write};
+use std::fs::{File, OpenOptions};
+use std::io::{stdin, stdout, BufReader, BufWriter, IsTerminal, Read, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use ansi_term::Color::*;
-use clap::{App, Arg};
+use chrono::{Datelike, Local};
+use clap::{App, AppSettings, Arg, ArgMatches, Shell};
 use text_io::*;
 
+use crate::color::{color, disabled_by_env, set_enabled};
+use crate::config::*;
 use crate::diary::*;
+use crate::error::DiaryError;
+use crate::hash::HashAlgorithm;
 
+mod color;
+mod config;
 mod diary;
+mod error;
+mod hash;
+mod json;
+mod language;
+mod progress;
+mod regex;
+mod templates;
 
-fn main() {
-    #[cfg(windows)] {
-        if let Err(e) = ansi_term::enable_ansi_support() {
-            panic!("Error: couldn't enable ansi escape codes: GetLastError = {}", e);
+/// Set by the global `--yes` flag, answers every confirmation prompt with yes
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Global flags which only apply when starting `didi`, the repl rejects them
+const STARTUP_FLAGS: [(&str, &str); 6] = [("yes", "--yes"), ("dryrun", "--dry-run"), ("quiet", "--quiet"),
+                                          ("nocolor", "--no-color"), ("diary", "--diary"), ("databases", "--databases")];
+
+/// Subcommands which write their output into the file given by the global `--output` flag
+const OUTPUT_COMMANDS: [&str; 8] = ["list", "today", "search", "review list", "random", "show", "describe", "export"];
+
+/// Subcommands which honour the global `--dry-run` flag
+const DRY_RUN_COMMANDS: [&str; 4] = ["hide", "unhide", "delete", "import"];
+
+/// Asks the user a yes/no `question`, an empty answer results in `default`.
+/// Always returns `true` when the global `--yes` flag is given.
+fn confirm(question: &str, default: bool) -> bool {
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    print!("{} {} ", question, hint);
+
+    if ASSUME_YES.load(Ordering::Relaxed) {
+        println!("y");
+        return true;
+    }
+
+    stdout().flush().unwrap();
+    let answer: String = read!("{}\n");
+    match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" => true,
+        _ => false
+    }
+}
+
+/// Reads multiline content from stdin until a blank line, appending to `content`
+fn read_content(mut content: String) -> String {
+    while let Ok(n) = stdin().read_line(&mut content) {
+        if n == 0 || content.ends_with("\r\n\r\n") || content.ends_with("\n\n") {
+            break;
         }
     }
+    content
+}
+
+/// Temporary file which is removed again when it gets dropped, also if the editor fails
+struct TempFile(PathBuf);
+
+impl TempFile {
+    /// Creates a new file with a random name only the user can read containing `content`
+    fn create(content: &str) -> Result<Self, DiaryError> {
+        let mut bytes = [0u8; 16];
+        getrandom::getrandom(&mut bytes)
+            .map_err(|e| DiaryError::Invalid(format!("couldn't create temporary file name: {}", e)))?;
+        let path = std::env::temp_dir().join(format!("didi-{}.txt", hex::encode(bytes)));
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+
+        let mut file = options.open(&path)
+            .map_err(|e| DiaryError::Invalid(format!("couldn't create temporary file '{}': {}", path.display(), e)))?;
+        let temp = TempFile(path);
+        file.write_all(content.as_bytes())
+            .map_err(|e| DiaryError::Invalid(format!("couldn't write temporary file '{}': {}", temp.0.display(), e)))?;
+        Ok(temp)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Lets the user write content in the editor given by `$VISUAL` or `$EDITOR` (`vi` if neither is set),
+/// the editor opens a temporary file containing `initial`. Returns the saved text as it is.
+fn read_from_editor(initial: &str) -> Result<String, DiaryError> {
+    let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR"))
+        .ok().filter(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"));
+    let file = TempFile::create(initial)?;
+
+    // the editor may come with arguments like `code --wait`
+    let mut parts = editor.split_whitespace();
+    let status = Command::new(parts.next().unwrap()).args(parts).arg(&file.0).status();
+    match status {
+        Ok(s) if s.success() => {}
+        Ok(s) => return Err(DiaryError::Invalid(format!("editor '{}' exited with {}", editor, s))),
+        Err(e) => return Err(DiaryError::Invalid(format!("couldn't start editor '{}': {}", editor, e)))
+    }
+
+    std::fs::read_to_string(&file.0)
+        .map_err(|e| DiaryError::Invalid(format!("couldn't read temporary file '{}': {}", file.0.display(), e)))
+}
+
+/// Opens `path` with the application registered for it using `open` on macOS, `start` on Windows
+/// and `xdg-open` everywhere else
+fn open_with_default_app(path: &Path) -> Result<(), DiaryError> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        let mut c = Command::new("cmd");
+        c.args(["/C", "start", ""]);
+        c
+    } else {
+        Command::new("xdg-open")
+    };
+
+    match command.arg(path).status() {
+        Ok(s) if s.success() => Ok(()),
+        Ok(s) => Err(DiaryError::Invalid(format!("opening '{}' failed with {}", path.display(), s))),
+        Err(e) => Err(DiaryError::Invalid(format!("couldn't open '{}': {}", path.display(), e)))
+    }
+}
+
+/// Reads the whole file at `path` as it is
+fn read_file(path: &str) -> Result<String, DiaryError> {
+    std::fs::read_to_string(path).map_err(|e| DiaryError::Invalid(format!("couldn't read file '{}': {}", path, e)))
+}
+
+/// Warns about keywords contained in other keywords and asks `question` if there are any
+fn accept_keywords(keywords: &[String], question: &str) -> bool {
+    let overlapping = overlapping_keywords(keywords);
+    for (a, b) in &overlapping {
+        println!("{} keyword '{}' is contained in '{}'", color(Yellow).paint("Warning:"),
+                 color(Cyan).paint(a), color(Cyan).paint(b));
+    }
+
+    overlapping.is_empty() || confirm(question, true)
+}
+
+/// Validates that an argument is a positive number (u64)
+fn validate_id(a: String) -> Result<(), String> {
+    match a.parse::<u64>() {
+        Err(_) => Err(String::from("argument only accepts positive numbers (u64)")),
+        _ => Ok(())
+    }
+}
+
+/// Validates that an argument is an entry id, optionally prefixed with a database name
+fn validate_entry_id(a: String) -> Result<(), String> {
+    EntryId::parse(&a).map(|_| ())
+}
+
+/// Validates that an argument has the form `key=value`
+fn validate_meta(a: String) -> Result<(), String> {
+    match a.split_once('=') {
+        Some((k, _)) if !k.trim().is_empty() => Ok(()),
+        _ => Err(String::from("argument has to be of the form key=value"))
+    }
+}
+
+/// Validates that an argument is a mood from 1 to 5
+fn validate_mood(a: String) -> Result<(), String> {
+    match a.parse::<u8>() {
+        Ok(1..=5) => Ok(()),
+        _ => Err(String::from("mood has to be a number from 1 to 5"))
+    }
+}
+
+/// Reads the paths given by `--attach` and warns about the ones which don't exist
+fn attachments(matches: &ArgMatches) -> Vec<String> {
+    let paths: Vec<String> = matches.values_of("attach").map_or(Vec::new(), |v| v.map(String::from).collect());
+    for p in paths.iter().filter(|p| !Path::new(p).exists()) {
+        println!("{} the attachment '{}' doesn't exist", color(Yellow).paint("Warning:"), p);
+    }
+    paths
+}
+
+/// Splits `key=value` arguments into pairs
+fn parse_meta(values: Option<clap::Values>) -> Vec<(String, String)> {
+    values.map_or(Vec::new(), |v| {
+        v.map(|m| {
+            let (key, value) = m.split_once('=').unwrap();
+            (key.trim().to_string(), value.trim().to_string())
+        }).collect()
+    })
+}
+
+/// Reads the display flags shared by `list` and `search`, flags which aren't given fall back to the config
+fn display_options(matches: &ArgMatches, config: &Config) -> DisplayOptions {
+    let options = DisplayOptions {
+        date: !(matches.is_present("nodate") || config.display.nodate),
+        id: matches.is_present("id") || config.display.id,
+        hash: matches.is_present("hash") || config.display.hash,
+        keywords: matches.is_present("keywords") || config.display.keywords,
+        content: !(matches.is_present("nocontent") || config.display.nocontent),
+        hidden: matches.is_present("hidden") || config.display.hidden,
+        only_hidden: matches.is_present("onlyhidden"),
+        pinned_only: matches.is_present("pinnedonly"),
+        match_count: matches.is_present("matches"),
+        language: matches.is_present("language"),
+        word_count: matches.is_present("wordcount"),
+        metadata: matches.is_present("metadata"),
+        attachments: matches.is_present("attachments"),
+        mood: matches.is_present("mood"),
+        relative: matches.is_present("relative"),
+        group_by: matches.value_of("groupby").map(|g| GroupBy::from_name(g).unwrap()),
+        compact: matches.is_present("compact"),
+        preview: matches.value_of("preview").map(|n| n.parse().unwrap()),
+        filter_metadata: parse_meta(matches.values_of("metafilter")),
+        summary: matches.is_present("summaryonly"),
+        timezone: matches.value_of("tz").map(|t| parse_timezone(t).unwrap()),
+        filter_language: matches.value_of("lang").map(|l| l.to_lowercase()),
+        // `--since-last-run` is filled in by `last_run_options` once the database is open
+        since: matches.value_of("since").map(|s| parse_relative(s).unwrap()),
+        from: matches.value_of("from").or(matches.value_of("on")).map(|d| parse_day(d, false).unwrap()),
+        to: matches.value_of("to").or(matches.value_of("on")).map(|d| parse_day(d, true).unwrap()),
+        sort: matches.value_of("sort").map(|s| SortField::from_name(s).unwrap()),
+        reverse: matches.is_present("reverse"),
+        json: matches.is_present("json"),
+        limit: match matches.value_of("limit") {
+            Some(l) => Some(l.parse().unwrap()),
+            None if matches.is_present("allresults") => None,
+            None => config.max_results
+        },
+        offset: if matches.is_present("limit") || matches.is_present("offset") {
+            Some(matches.value_of("offset").map_or(0, |o| o.parse().unwrap()))
+        } else {
+            None
+        },
+    };
+
+    match matches.value_of("displayformat") {
+        Some(f) => options.with_format(DisplayFormat::from_name(f).unwrap()),
+        None => options
+    }
+}
+
+/// Reads `--format` of `export`, the export format of the config is used if it isn't given
+fn export_format(matches: &ArgMatches, config: &Config) -> ExportFormat {
+    matches.value_of("format").map_or(config.export_format, |f| ExportFormat::from_name(f).unwrap())
+}
+
+/// Redirects the listings of `diary` into the file given by `--output`, colors get disabled
+fn set_output(diary: &mut Diary, matches: &ArgMatches) -> Result<(), DiaryError> {
+    if let Some(path) = matches.value_of("output") {
+        diary.set_output(Box::new(BufWriter::new(create_file(path)?)));
+        set_enabled(false);
+    }
+    Ok(())
+}
+
+/// Creates the file at `path`, an existing one is truncated
+fn create_file(path: &str) -> Result<File, DiaryError> {
+    File::create(path).map_err(|e| DiaryError::Invalid(format!("couldn't create file '{}': {}", path, e)))
+}
 
+/// Reads the databases given by the global `--databases` flag
+fn databases(matches: &ArgMatches) -> Option<Vec<String>> {
+    matches.values_of("databases").map(|v| v.map(|s| s.to_string()).collect())
+}
+
+/// Opens the databases for `list` and `search`, with `--json` the welcome message is left out
+fn open_listing(matches: &ArgMatches) -> Result<Diary, DiaryError> {
+    if matches.is_present("json") {
+        Diary::open_combined_quietly(databases(matches), false)
+    } else {
+        Diary::open_combined(databases(matches), false)
+    }
+}
+
+/// Resolves the ids given to `hide` and `unhide`, either directly, by `--tag` or by date
+fn ids_to_update(diary: &mut Diary, matches: &ArgMatches) -> Result<Vec<EntryId>, DiaryError> {
+    let from = matches.value_of("from").map(|d| parse_day(d, false).unwrap());
+    let to = match (matches.value_of("before"), matches.value_of("to")) {
+        (Some(d), _) => Some(parse_day(d, false).unwrap()),
+        (_, Some(d)) => Some(parse_day(d, true).unwrap()),
+        _ => None
+    };
+
+    let mut ids: Vec<EntryId> = if let Some(t) = matches.value_of("tag") {
+        diary.ids_with_keyword(&t.to_lowercase())?
+    } else if from.is_some() || to.is_some() {
+        diary.ids_in_range(from, to)?
+    } else {
+        matches.values_of("tohide").unwrap()
+            .map(|s| EntryId::parse(s).unwrap()).collect()
+    };
+    ids.sort();
+    ids.dedup();
+    Ok(ids)
+}
+
+/// Builds the command line interface, also used to parse the commands of the repl
+fn build_app() -> App<'static, 'static> {
     let display_args = [
         Arg::with_name("nocontent")
             .short("n")
@@ -39,35 +338,385 @@ fn main() {
         Arg::with_name("hidden")
             .short("a")
             .long("hidden")
-            .help("Show hidden entries")];
+            .help("Show hidden entries"),
+        Arg::with_name("onlyhidden")
+            .long("only-hidden")
+            .conflicts_with("hidden")
+            .help("Show only hidden entries"),
+        Arg::with_name("language")
+            .short("l")
+            .long("language")
+            .help("Show language of entry"),
+        Arg::with_name("wordcount")
+            .short("w")
+            .long("wordcount")
+            .help("Show number of words in the content of entry"),
+        Arg::with_name("lang")
+            .long("lang")
+            .takes_value(true)
+            .help("Only show entries written in this language (ISO 639-1 code)"),
+        Arg::with_name("metadata")
+            .long("metadata")
+            .help("Show metadata of entry"),
+        Arg::with_name("attachments")
+            .long("attachments")
+            .help("Show attachments of entry"),
+        Arg::with_name("mood")
+            .long("mood")
+            .help("Show mood of entry"),
+        Arg::with_name("relative")
+            .long("relative")
+            .help("Show how long ago the entry was written"),
+        Arg::with_name("compact")
+            .long("compact")
+            .help("Collapse runs of three or more blank lines in the content"),
+        Arg::with_name("metafilter")
+            .long("meta-filter")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .validator(validate_meta)
+            .help("Only show entries with this metadata (key=value), can be repeated"),
+        Arg::with_name("tz")
+            .long("tz")
+            .takes_value(true)
+            .validator(|a| parse_timezone(&a).map(|_| ()))
+            .help("Show dates in this timezone, either UTC, an offset like +02:00 or a name like Europe/Vienna")];
+
+    // `list` calls it `--all`, `search` already uses that for matching every term
+    let all_results = Arg::with_name("allresults")
+        .long("all-results")
+        .help("Show all entries even if there are more than `max_results`");
 
     let tohide = Arg::with_name("tohide")
-        .required(true)
+        .required_unless_one(&["tag", "before", "from", "to"])
         .multiple(true)
-        .validator(|a| {
-            match a.parse::<u64>() {
-                Err(_) => Err(String::from("argument only accepts positive numbers (u64)")),
-                _ => Ok(())
-            }
-        })
-        .help("Ids of the entries to update");
+        .validator(validate_entry_id)
+        .help("Ids of the entries to update, prefixed with the database name when combining databases");
+
+    let tag = Arg::with_name("tag")
+        .short("t")
+        .long("tag")
+        .takes_value(true)
+        .conflicts_with("tohide")
+        .help("Update all entries with this keyword");
+
+    let hide_by_date = [
+        Arg::with_name("before")
+            .long("before")
+            .takes_value(true)
+            .validator(|a| parse_day(&a, false).map(|_| ()))
+            .conflicts_with_all(&["tohide", "tag", "from", "to"])
+            .help("Update all entries written before this date (YYYY-MM-DD)"),
+        Arg::with_name("from")
+            .long("from")
+            .takes_value(true)
+            .validator(|a| parse_day(&a, false).map(|_| ()))
+            .conflicts_with_all(&["tohide", "tag"])
+            .help("Update all entries written on or after this date (YYYY-MM-DD)"),
+        Arg::with_name("to")
+            .long("to")
+            .takes_value(true)
+            .validator(|a| parse_day(&a, true).map(|_| ()))
+            .conflicts_with_all(&["tohide", "tag"])
+            .help("Update all entries written on or before this date (YYYY-MM-DD)")];
+
+    let date_range = [
+        Arg::with_name("from")
+            .long("from")
+            .takes_value(true)
+            .validator(|a| parse_day(&a, false).map(|_| ()))
+            .help("Only show entries written on or after this date (YYYY-MM-DD)"),
+        Arg::with_name("to")
+            .long("to")
+            .takes_value(true)
+            .validator(|a| parse_day(&a, true).map(|_| ()))
+            .help("Only show entries written on or before this date (YYYY-MM-DD)"),
+        Arg::with_name("on")
+            .long("on")
+            .takes_value(true)
+            .conflicts_with_all(&["from", "to"])
+            .validator(|a| parse_day(&a, true).map(|_| ()))
+            .help("Only show entries written on this date (YYYY-MM-DD)")];
+
+    let after_id = Arg::with_name("afterid")
+        .long("after-id")
+        .takes_value(true)
+        .validator(validate_id)
+        .help("Only include entries with an id greater than this");
+
+    let pagination = [
+        Arg::with_name("limit")
+            .long("limit")
+            .takes_value(true)
+            .validator(validate_id)
+            .conflicts_with("allresults")
+            .help("Show at most this many entries"),
+        Arg::with_name("offset")
+            .long("offset")
+            .takes_value(true)
+            .validator(validate_id)
+            .help("Skip this many entries before showing any")];
+
+    let sorting = [
+        Arg::with_name("sort")
+            .long("sort")
+            .takes_value(true)
+            .possible_values(&SortField::NAMES)
+            .help("Sort the entries by this field"),
+        Arg::with_name("reverse")
+            .short("r")
+            .long("reverse")
+            .help("Reverse the order of the entries")];
+
+    let display_format = Arg::with_name("displayformat")
+        .long("format")
+        .takes_value(true)
+        .value_name("format")
+        .possible_values(&DisplayFormat::NAMES)
+        .help("Show entries using a preset instead of the display flags");
+
+    let preview = Arg::with_name("preview")
+        .long("preview")
+        .value_name("chars")
+        .takes_value(true)
+        .validator(validate_id)
+        .help("Shorten the content of every entry to this many characters");
+
+    let json = Arg::with_name("json")
+        .long("json")
+        .help("Write the entries as JSON, other messages go to stderr");
+
+    let since_last_run = Arg::with_name("sincelastrun")
+        .long("since-last-run")
+        .help("Only show entries added since the last run of list or search");
 
-    let matches = App::new("Digital Diary")
+    let since = Arg::with_name("since")
+        .long("since")
+        .takes_value(true)
+        .value_name("duration")
+        .conflicts_with("sincelastrun")
+        .validator(|a| parse_relative(&a).map(|_| ()))
+        .help("Only show entries written within this duration, e.g. 7d, 2w, 3m or 1y");
+
+    App::new("Digital Diary")
         .version(env!("CARGO_PKG_VERSION"))
         .author("Thomas Lienbacher <lienbacher.tom@gmail.com>")
         .about("A small CLI diary used to document your life.")
+        .arg(Arg::with_name("yes")
+            .short("y")
+            .long("yes")
+            .global(true)
+            .help("Automatically answer yes to all confirmation prompts"))
+        .arg(Arg::with_name("dryrun")
+            .long("dry-run")
+            .global(true)
+            .help("Show what hide, unhide, delete and import would change without writing anything"))
+        .arg(Arg::with_name("quiet")
+            .short("q")
+            .long("quiet")
+            .global(true)
+            .help("Don't show progress bars"))
+        .arg(Arg::with_name("nocolor")
+            .long("no-color")
+            .global(true)
+            .help("Print the output without colors, also done if NO_COLOR is set"))
+        .arg(Arg::with_name("diary")
+            .long("diary")
+            .takes_value(true)
+            .global(true)
+            .validator(|a| validate_diary_name(&a))
+            .help("Use the diary with this name instead of the default one"))
+        .arg(Arg::with_name("databases")
+            .long("databases")
+            .takes_value(true)
+            .multiple(true)
+            .require_delimiter(true)
+            .global(true)
+            .help("Comma separated databases to combine for list, search and stats"))
+        .arg(Arg::with_name("output")
+            .short("o")
+            .long("output")
+            .takes_value(true)
+            .global(true)
+            .help("Write the output of listings and exports to a file without colors"))
         .subcommand(
             App::new("create")
                 .about("Creates the database")
+                .arg(Arg::with_name("hash")
+                    .long("hash")
+                    .takes_value(true)
+                    .possible_values(&HashAlgorithm::NAMES)
+                    .default_value("sha256")
+                    .help("Algorithm used for the hashes of the entries"))
         )
         .subcommand(
             App::new("add")
                 .about("Adds an entry")
+                .arg(Arg::with_name("detectlanguage")
+                    .long("detect-language")
+                    .help("Detect and store the language of the content"))
+                .arg(Arg::with_name("attach")
+                    .long("attach")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .value_name("path")
+                    .validator(|a| if a.contains(';') { Err(String::from("paths can't contain ';'")) } else { Ok(()) })
+                    .help("Link a file to the entry, can be repeated"))
+                .arg(Arg::with_name("mood")
+                    .long("mood")
+                    .takes_value(true)
+                    .value_name("N")
+                    .validator(validate_mood)
+                    .help("Mood from 1 (bad) to 5 (great)"))
+                .arg(Arg::with_name("meta")
+                    .long("meta")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .validator(validate_meta)
+                    .help("Store custom metadata (key=value), can be repeated"))
+                .arg(Arg::with_name("title")
+                    .long("title")
+                    .takes_value(true)
+                    .conflicts_with("firstlineastitle")
+                    .help("Title of the entry instead of asking for it"))
+                .arg(Arg::with_name("content")
+                    .long("content")
+                    .takes_value(true)
+                    .help("Content of the entry instead of asking for it, piped input is used if omitted"))
+                .arg(Arg::with_name("editor")
+                    .short("e")
+                    .long("editor")
+                    .conflicts_with("content")
+                    .help("Write the content in $VISUAL or $EDITOR keeping its formatting"))
+                .arg(Arg::with_name("contentfile")
+                    .long("content-file")
+                    .takes_value(true)
+                    .value_name("path")
+                    .conflicts_with_all(&["content", "editor"])
+                    .help("Read the content from this file and store it exactly as written"))
+                .arg(Arg::with_name("template")
+                    .long("template")
+                    .takes_value(true)
+                    .value_name("name")
+                    .conflicts_with_all(&["content", "contentfile"])
+                    .validator(|a| templates::validate_name(&a))
+                    .help("Start the content with a stored template"))
+                .arg(Arg::with_name("allowempty")
+                    .long("allow-empty")
+                    .help("Add the entry even if its content is empty"))
+                .arg(Arg::with_name("keywords")
+                    .long("keywords")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .conflicts_with("keywordsfromtitle")
+                    .help("Keywords of the entry separated by spaces instead of asking for them"))
+                .arg(Arg::with_name("keywordsfromtitle")
+                    .long("keywords-from-title")
+                    .help("Use the significant words of the title as keywords instead of asking for them"))
+                .arg(Arg::with_name("firstlineastitle")
+                    .long("first-line-as-title")
+                    .help("Use the first line of the content as title instead of asking for one"))
+                .arg(Arg::with_name("striptitle")
+                    .long("strip-title")
+                    .requires("firstlineastitle")
+                    .help("Remove the first line from the content when it is used as title"))
         )
+        .subcommand(
+            App::new("edit")
+                .about("Edits the title, content and keywords of an entry")
+                .arg(Arg::with_name("entry")
+                    .required(true)
+                    .validator(validate_id)
+                    .help("Id of the entry"))
+                .arg(Arg::with_name("editor")
+                    .short("e")
+                    .long("editor")
+                    .help("Edit the content in $VISUAL or $EDITOR keeping its formatting"))
+                .arg(Arg::with_name("contentfile")
+                    .long("content-file")
+                    .takes_value(true)
+                    .value_name("path")
+                    .conflicts_with("editor")
+                    .help("Replace the content with this file and store it exactly as written"))
+                .arg(Arg::with_name("attach")
+                    .long("attach")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .value_name("path")
+                    .validator(|a| if a.contains(';') { Err(String::from("paths can't contain ';'")) } else { Ok(()) })
+                    .help("Link another file to the entry, can be repeated"))
+                .arg(Arg::with_name("mood")
+                    .long("mood")
+                    .takes_value(true)
+                    .value_name("N")
+                    .validator(validate_mood)
+                    .help("Change the mood to a number from 1 (bad) to 5 (great)")))
+        .subcommand(
+            App::new("tag")
+                .about("Adds or removes keywords of an entry")
+                .arg(Arg::with_name("entry")
+                    .required(true)
+                    .validator(validate_id)
+                    .help("Id of the entry"))
+                .arg(Arg::with_name("add")
+                    .long("add")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .value_name("keyword")
+                    .required_unless("remove")
+                    .help("Keyword to add (repeatable)"))
+                .arg(Arg::with_name("remove")
+                    .long("remove")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .value_name("keyword")
+                    .help("Keyword to remove (repeatable)")))
         .subcommand(
             App::new("list")
                 .about("Lists all entries")
-                .args(&display_args))
+                .args(&display_args)
+                .arg(all_results.clone().long("all").visible_alias("all-results"))
+                .arg(&after_id)
+                .arg(&since_last_run)
+                .arg(&since)
+                .args(&date_range)
+                .args(&pagination)
+                .args(&sorting)
+                .arg(&json)
+                .arg(&preview)
+                .arg(&display_format)
+                .arg(Arg::with_name("groupby")
+                    .long("group-by")
+                    .takes_value(true)
+                    .possible_values(&GroupBy::NAMES)
+                    .help("Print the entries in groups with a header before each one"))
+                .arg(Arg::with_name("every")
+                    .long("every")
+                    .takes_value(true)
+                    .validator(|a| {
+                        match a.parse::<usize>() {
+                            Ok(n) if n > 0 => Ok(()),
+                            _ => Err(String::from("argument only accepts numbers greater than 0"))
+                        }
+                    })
+                    .help("Only show every nth entry in date order"))
+                .arg(Arg::with_name("pinnedonly")
+                    .long("pinned-only")
+                    .help("Only show pinned entries")))
+        .subcommand(
+            App::new("today")
+                .about("Lists the entries written today")
+                .args(&display_args)
+                .arg(&all_results)
+                .arg(Arg::with_name("add")
+                    .long("add")
+                    .help("Add a new entry instead")))
         .subcommand(
             App::new("search")
                 .about("Searches for entries")
@@ -76,99 +725,1052 @@ fn main() {
                     .multiple(true)
                     .help("Keywords to search for"))
                 .args(&display_args)
+                .arg(&all_results)
+                .arg(&since_last_run)
+                .arg(&since)
+                .args(&date_range)
+                .args(&pagination)
+                .args(&sorting)
+                .arg(&json)
+                .arg(&preview)
+                .arg(&display_format)
+                .arg(Arg::with_name("matches")
+                    .short("m")
+                    .long("matches")
+                    .help("Show how often the search terms appear in each entry"))
+                .arg(Arg::with_name("all")
+                    .long("all")
+                    .conflicts_with("any")
+                    .help("Only show entries matching every search term"))
+                .arg(Arg::with_name("any")
+                    .long("any")
+                    .help("Show entries matching any of the search terms (default)"))
+                .arg(Arg::with_name("fulltext")
+                    .long("fulltext")
+                    .help("Search the title, keywords and content using the full-text index"))
+                .arg(Arg::with_name("fuzzy")
+                    .long("fuzzy")
+                    .conflicts_with_all(&["fulltext", "phrase"])
+                    .help("Match terms whose letters appear in order with others in between, best matches first"))
+                .arg(Arg::with_name("threshold")
+                    .long("threshold")
+                    .takes_value(true)
+                    .requires("fuzzy")
+                    .validator(|a| a.parse::<i64>().map(|_| ()).map_err(|_| String::from("argument only accepts numbers")))
+                    .help("Minimum score of a fuzzy match, higher values require closer matches"))
+                .arg(Arg::with_name("content")
+                    .long("content")
+                    .conflicts_with_all(&["fulltext", "fuzzy"])
+                    .help("Only search the content instead of the title and keywords"))
+                .arg(Arg::with_name("regex")
+                    .long("regex")
+                    .requires("content")
+                    .conflicts_with("phrase")
+                    .help("Treat every search term as a regular expression matched against the content"))
+                .arg(Arg::with_name("phrase")
+                    .long("phrase")
+                    .help("Match every quoted search term as a whole keyword or part of the title"))
+                .arg(Arg::with_name("summaryonly")
+                    .long("summary-only")
+                    .conflicts_with("displayformat")
+                    .help("Only show id, date and title of every match on a single line"))
         )
         .subcommand(App::new("hide")
             .about("Hide one or more entries")
             .arg(&tohide)
+            .arg(&tag)
+            .args(&hide_by_date)
         )
         .subcommand(App::new("unhide")
             .about("Unhide one or more entries")
             .arg(&tohide)
+            .arg(&tag)
+            .args(&hide_by_date)
+        )
+        .subcommand(App::new("delete")
+            .about("Permanently deletes one or more entries")
+            .arg(Arg::with_name("todelete")
+                .required(true)
+                .multiple(true)
+                .validator(validate_entry_id)
+                .help("Ids of the entries to delete, prefixed with the database name when combining databases"))
+            .arg(Arg::with_name("force")
+                .short("f")
+                .long("force")
+                .help("Delete without asking for confirmation"))
+        )
+        .subcommand(App::new("pin")
+            .about("Pins one or more entries so they are listed first")
+            .arg(Arg::with_name("ids")
+                .required(true)
+                .multiple(true)
+                .validator(validate_id)
+                .help("Ids of the entries to pin"))
+        )
+        .subcommand(App::new("unpin")
+            .about("Unpins one or more entries")
+            .arg(Arg::with_name("ids")
+                .required(true)
+                .multiple(true)
+                .validator(validate_id)
+                .help("Ids of the entries to unpin"))
+        )
+        .subcommand(App::new("repl")
+            .about("Opens the diary once and runs add, list and search commands read from stdin")
         )
-        .get_matches();
+        .subcommand(App::new("undo")
+            .about("Reverts the last hide, unhide or delete")
+        )
+        .subcommand(App::new("review")
+            .about("Manages the queue of entries to review later")
+            .subcommand(App::new("add")
+                .about("Adds one or more entries to the review queue")
+                .arg(Arg::with_name("ids")
+                    .required(true)
+                    .multiple(true)
+                    .validator(validate_id)
+                    .help("Ids of the entries to review")))
+            .subcommand(App::new("list")
+                .about("Lists all entries in the review queue")
+                .args(&display_args)
+                .arg(&all_results))
+            .subcommand(App::new("done")
+                .about("Removes one or more entries from the review queue")
+                .arg(Arg::with_name("ids")
+                    .required(true)
+                    .multiple(true)
+                    .validator(validate_id)
+                    .help("Ids of the reviewed entries")))
+        )
+        .subcommand(App::new("template")
+            .about("Manages the templates new entries can start with")
+            .subcommand(App::new("list")
+                .about("Lists all templates"))
+            .subcommand(App::new("show")
+                .about("Prints a template")
+                .arg(Arg::with_name("name")
+                    .required(true)
+                    .help("Name of the template")))
+            .subcommand(App::new("add")
+                .about("Creates or changes a template in $VISUAL or $EDITOR")
+                .arg(Arg::with_name("name")
+                    .required(true)
+                    .validator(|a| templates::validate_name(&a))
+                    .help("Name of the template"))
+                .arg(Arg::with_name("file")
+                    .long("file")
+                    .takes_value(true)
+                    .value_name("path")
+                    .help("Read the template from this file instead of opening the editor")))
+            .subcommand(App::new("remove")
+                .about("Deletes a template")
+                .arg(Arg::with_name("name")
+                    .required(true)
+                    .help("Name of the template")))
+        )
+        .subcommand(App::new("rebuild-keywords")
+            .about("Suggests keywords for entries without any based on their content")
+        )
+        .subcommand(App::new("keywords")
+            .about("Lists all keywords and how many entries use them")
+            .arg(Arg::with_name("alpha")
+                .long("alpha")
+                .help("Sort the keywords alphabetically instead of by count"))
+        )
+        .subcommand(App::new("find-orphan-keywords")
+            .about("Lists keywords which are used by only one entry")
+        )
+        .subcommand(App::new("stats")
+            .about("Shows statistics about the diary")
+            .arg(Arg::with_name("calendar")
+                .long("calendar")
+                .value_name("year")
+                .takes_value(true)
+                .min_values(0)
+                .validator(|a| {
+                    match a.parse::<i32>() {
+                        Ok(y) if (1..=9999).contains(&y) => Ok(()),
+                        _ => Err(String::from("argument has to be a year"))
+                    }
+                })
+                .help("Show a heatmap of entries per day of the year, the current year by default"))
+        )
+        .subcommand(App::new("count")
+            .about("Prints the number of entries")
+            .arg(Arg::with_name("searchfor")
+                .multiple(true)
+                .help("Only count entries matching these keywords like search"))
+            .arg(Arg::with_name("hidden")
+                .short("a")
+                .long("hidden")
+                .help("Count hidden entries"))
+        )
+        .subcommand(App::new("completions")
+            .about("Writes a completion script for a shell to stdout")
+            .setting(AppSettings::Hidden)
+            .arg(Arg::with_name("shell")
+                .required(true)
+                .possible_values(&Shell::variants())
+                .help("Shell to generate the completions for")))
+        .subcommand(App::new("diaries")
+            .about("Lists the diaries in the home directory")
+        )
+        .subcommand(App::new("path")
+            .about("Prints the path of the database, also if it doesn't exist yet")
+        )
+        .subcommand(App::new("open")
+            .about("Opens the database in the application registered for SQLite files")
+        )
+        .subcommand(App::new("verify")
+            .about("Checks the integrity of all entries using their hashes")
+            .arg(Arg::with_name("interactive")
+                .short("i")
+                .long("interactive")
+                .help("Go through every mismatch and choose whether to store the recomputed hash"))
+        )
+        .subcommand(App::new("random")
+            .about("Shows randomly chosen entries to rediscover them")
+            .arg(Arg::with_name("n")
+                .default_value("1")
+                .validator(validate_id)
+                .help("How many entries to show"))
+            .args(&display_args)
+            .arg(&all_results)
+        )
+        .subcommand(App::new("show")
+            .about("Shows entries by their ids")
+            .arg(Arg::with_name("entry")
+                .required(true)
+                .multiple(true)
+                .validator(validate_entry_id)
+                .help("Ids of the entries, prefixed with the database name when combining databases"))
+            .args(&display_args)
+            .arg(&all_results)
+            .arg(Arg::with_name("related")
+                .short("r")
+                .long("related")
+                .takes_value(true)
+                .value_name("n")
+                .min_values(0)
+                .max_values(1)
+                .validator(validate_id)
+                .help("Also list up to n (default 5) entries sharing the most keywords with it, \
+                       only for a single entry"))
+        )
+        .subcommand(App::new("describe")
+            .about("Shows the raw stored data of an entry")
+            .arg(Arg::with_name("id")
+                .required(true)
+                .validator(validate_entry_id)
+                .help("Id of the entry, prefixed with the database name when combining databases"))
+        )
+        .subcommand(App::new("import")
+            .about("Imports entries from a JSON file written by export")
+            .arg(Arg::with_name("file")
+                .required(true)
+                .help("JSON file to import"))
+            .arg(Arg::with_name("check")
+                .long("check")
+                .help("Only validate the file and report what would be imported without writing anything"))
+        )
+        .subcommand(App::new("export")
+            .about("Exports all entries to a file or directory")
+            .arg(Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&ExportFormat::NAMES)
+                .help("Format of the export, defaults to the export format of the config"))
+            .arg(Arg::with_name("pretty")
+                .long("pretty")
+                .help("Indent the JSON output for reading by hand"))
+            .arg(Arg::with_name("dir")
+                .long("dir")
+                .takes_value(true)
+                .conflicts_with("output")
+                .help("Directory to write every entry into its own file"))
+            .arg(Arg::with_name("splitby")
+                .long("split-by")
+                .takes_value(true)
+                .possible_values(&["year", "month"])
+                .requires("dir")
+                .help("Split the exported files into directories by date"))
+            .arg(Arg::with_name("hidden")
+                .short("a")
+                .long("hidden")
+                .help("Export hidden entries"))
+            .arg(&after_id)
+            .arg(Arg::with_name("ids")
+                .multiple(true)
+                .validator(validate_id)
+                .help("Ids of the entries to export, all entries if none are given"))
+        )
+}
 
-    match matches.subcommand() {
-        ("create", Some(_)) => {
-            let url = Diary::create();
-            println!("Created database at '{}'!", Cyan.paint(url.as_path().to_str().unwrap()))
+fn main() -> ExitCode {
+    let result = start();
+    let _ = stdout().flush();
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
         }
-        ("add", Some(_)) => {
-            let mut diary = Diary::open();
+    }
+}
 
-            print!("{}", Cyan.paint("Title: "));
-            stdout().flush().unwrap();
-            let title: String = read!("{}\n");
+/// Parses the arguments, applies the global flags and runs the subcommand
+fn start() -> Result<(), DiaryError> {
+    #[cfg(windows)] {
+        if let Err(e) = ansi_term::enable_ansi_support() {
+            return Err(DiaryError::Invalid(format!("couldn't enable ansi escape codes: GetLastError = {}", e)));
+        }
+    }
 
-            print!("{}", Cyan.paint("Content: "));
-            stdout().flush().unwrap();
-            let mut raw_content = String::new();
-            while stdin().read_line(&mut raw_content).is_ok() {
-                if raw_content.ends_with("\r\n\r\n") || raw_content.ends_with("\n\n") {
-                    break;
+    let app = if disabled_by_env() { build_app().global_setting(AppSettings::ColorNever) } else { build_app() };
+    let matches = app.get_matches();
+
+    ASSUME_YES.store(matches.is_present("yes"), Ordering::Relaxed);
+    progress::set_enabled(!matches.is_present("quiet"));
+    if matches.is_present("nocolor") || disabled_by_env() {
+        set_enabled(false);
+    }
+    if let Some(name) = matches.value_of("diary") {
+        select_diary(name);
+    }
+    let config = Config::load()?;
+
+    run(&matches, &config)
+}
+
+/// Arguments of `add` without any flags, used by `today --add` since the flags of `today`
+/// mean something else for `add`, e.g. `-k` shows keywords instead of giving them
+fn default_add_args() -> ArgMatches<'static> {
+    build_app().get_matches_from(["didi", "add"]).subcommand_matches("add").unwrap().clone()
+}
+
+/// Runs `add`, the prompts are skipped for the values given in `matches`. Piped input is only
+/// used as content if `piped_content` is `true`.
+fn add_entry(diary: &mut Diary, matches: &ArgMatches, config: &Config, piped_content: bool) -> Result<(), DiaryError> {
+    diary.set_max_content_length(config.max_content_length);
+    let first_line_as_title = matches.is_present("firstlineastitle");
+    // with flags given piped input is the whole content instead of answers to the prompts
+    let use_editor = matches.is_present("editor");
+    let content_file = matches.value_of("contentfile");
+    let read_piped = piped_content && matches.value_of("content").is_none() && !use_editor && content_file.is_none()
+        && !stdin().is_terminal()
+        && (matches.is_present("title") || matches.is_present("keywords"));
+
+    let template = match matches.value_of("template") {
+        Some(name) => templates::render(&templates::load(name).map_err(DiaryError::Invalid)?),
+        None => String::new()
+    };
+
+    let mut title = String::new();
+    if let Some(t) = matches.value_of("title") {
+        title = t.to_string();
+    } else if !first_line_as_title {
+        print!("{}", color(Cyan).paint("Title: "));
+        stdout().flush().unwrap();
+        title = read!("{}\n");
+    }
+    // a title given up front is checked before asking for the rest of the entry
+    if !first_line_as_title {
+        Diary::check_title(&title).map_err(DiaryError::Invalid)?;
+    }
+
+    let mut raw_content = if let Some(c) = matches.value_of("content") {
+        c.to_string()
+    } else if use_editor {
+        read_from_editor(&template)?
+    } else if let Some(path) = content_file {
+        read_file(path)?
+    } else if read_piped {
+        let mut c = template;
+        stdin().read_to_string(&mut c)
+            .map_err(|e| DiaryError::Invalid(format!("couldn't read content from stdin: {}", e)))?;
+        c
+    } else {
+        // the typed content is appended to the template
+        print!("{}{}", color(Cyan).paint("Content: "), template);
+        stdout().flush().unwrap();
+        read_content(template)
+    };
+
+    if first_line_as_title {
+        let (first, rest) = raw_content.split_once('\n').unwrap_or((&raw_content, ""));
+        title = first.to_string();
+        if matches.is_present("striptitle") {
+            raw_content = rest.to_string();
+        }
+    }
+
+    let (title, removed_title) = sanitize_text(&title);
+    let (raw_content, removed_content) = sanitize_text(&raw_content);
+    if removed_title + removed_content > 0 {
+        println!("{} removed {} control characters", color(Yellow).paint("Warning:"),
+                 color(Cyan).paint(format!("{}", removed_title + removed_content)));
+    }
+    // content written in an editor is formatted on purpose, content of a file is kept verbatim
+    let content = if content_file.is_some() {
+        raw_content
+    } else if use_editor {
+        ContentMode::Raw.apply(&raw_content)
+    } else {
+        config.content_mode.apply(&raw_content)
+    };
+    diary.check_content_length(&content).map_err(DiaryError::Invalid)?;
+    if content.trim().is_empty() {
+        if !matches.is_present("allowempty") {
+            return Err(DiaryError::Invalid(String::from("the content is empty, use --allow-empty to add it anyway")));
+        }
+        println!("{} the content is empty", color(Yellow).paint("Warning:"));
+    }
+
+    let keywords = if let Some(k) = matches.value_of("keywords") {
+        k.split_whitespace().map(|s| s.to_lowercase()).collect::<Vec<String>>()
+    } else if matches.is_present("keywordsfromtitle") {
+        let keywords = language::title_keywords(&title);
+        println!("{}{}", color(Cyan).paint("Keywords: "), keywords.join(" "));
+        keywords
+    } else if read_piped {
+        Vec::new()
+    } else {
+        print!("{}", color(Cyan).paint("Keywords: "));
+        stdout().flush().unwrap();
+        let raw: String = read!("{}\n");
+        raw.split_whitespace().map(|s| s.trim().to_lowercase()).collect::<Vec<String>>()
+    };
+
+    if !accept_keywords(&keywords, "Add anyway?") {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    let language = if matches.is_present("detectlanguage") {
+        let detected = language::detect(&content);
+        if detected.is_none() {
+            println!("{} couldn't detect the language of the content", color(Yellow).paint("Warning:"));
+        }
+        detected
+    } else {
+        None
+    };
+
+    let metadata = parse_meta(matches.values_of("meta"));
+
+    let mood = matches.value_of("mood").map(|m| m.parse().unwrap());
+
+    diary.add(keywords, title.trim().into(), content, language, metadata, attachments(matches), mood)
+}
+
+/// Reads the display options of `list` and `search`, with `--since-last-run` only the entries
+/// added since they last ran on `diary` are shown
+fn last_run_options(diary: &Diary, matches: &ArgMatches, config: &Config) -> DisplayOptions {
+    let options = display_options(matches, config);
+    if matches.is_present("sincelastrun") {
+        DisplayOptions { since: diary.last_run(), ..options }
+    } else {
+        options
+    }
+}
+
+/// Runs `list` on `diary`
+fn list_entries(diary: &mut Diary, matches: &ArgMatches, config: &Config) -> Result<(), DiaryError> {
+    set_output(diary, matches)?;
+
+    let after_id = matches.value_of("afterid").map_or(0, |a| a.parse().unwrap());
+    let every = matches.value_of("every").map(|a| a.parse().unwrap());
+
+    let now = Local::now();
+    diary.list_all(&last_run_options(diary, matches, config), after_id, every)?;
+    diary.store_last_run(now);
+    Ok(())
+}
+
+/// Runs `search` on `diary`
+fn search_entries(diary: &mut Diary, matches: &ArgMatches, config: &Config) -> Result<(), DiaryError> {
+    set_output(diary, matches)?;
+
+    let phrase = matches.is_present("phrase");
+    let options = last_run_options(diary, matches, config);
+    let all = matches.is_present("all");
+    let now = Local::now();
+
+    // regular expressions are used as given, splitting or lowercasing them would change their meaning
+    if matches.is_present("regex") {
+        let patterns = matches.values_of("searchfor").unwrap().map(String::from).collect();
+        diary.search_regex(patterns, &options, all)?;
+        diary.store_last_run(now);
+        return Ok(());
+    }
+
+    let keywords: Vec<String> = matches.values_of("searchfor").unwrap()
+        .flat_map(|s| {
+            if phrase {
+                vec![s.split_whitespace().collect::<Vec<&str>>().join(" ")]
+            } else {
+                s.split_whitespace().map(|w| w.to_string()).collect()
+            }
+        })
+        .map(|s| s.to_lowercase()).collect();
+
+    if matches.is_present("fulltext") {
+        diary.search_fulltext(keywords, &options, all)?;
+    } else if matches.is_present("fuzzy") {
+        let threshold = matches.value_of("threshold").map_or(0, |t| t.parse().unwrap());
+        diary.search_fuzzy(keywords, &options, all, threshold)?;
+    } else {
+        diary.search(keywords, &options, phrase, all, matches.is_present("content"))?;
+    }
+    diary.store_last_run(now);
+    Ok(())
+}
+
+/// Splits a line of the repl into arguments at whitespace, single and double quotes group words
+fn split_command_line(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote = None;
+
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err(String::from("unterminated quote"));
+    }
+    args.extend(current);
+    Ok(args)
+}
+
+/// Reads commands from stdin and runs them on a diary which stays open until `quit` or the end of the input.
+/// Only `add`, `list` and `search` are available.
+fn repl(config: &Config) -> Result<(), DiaryError> {
+    let mut diary = Diary::open()?;
+    let colored = color::enabled();
+    println!("Enter add, list or search with their usual arguments, quit to exit.");
+
+    loop {
+        print!("{}", color(Cyan).paint("didi> "));
+        stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            println!();
+            return Ok(());
+        }
+
+        let args = match split_command_line(&line) {
+            Ok(a) => a,
+            Err(e) => {
+                println!("{} {}", color(Red).paint("Error:"), e);
+                continue;
+            }
+        };
+
+        match args.first().map(|a| a.as_str()) {
+            None => continue,
+            Some("quit") | Some("exit") => return Ok(()),
+            Some("add") | Some("list") | Some("search") | Some("help") => {}
+            Some(other) => {
+                println!("'{}' isn't available in the repl, use add, list, search or quit.", other);
+                continue;
+            }
+        }
+
+        let matches = match build_app().get_matches_from_safe(std::iter::once(String::from("didi")).chain(args)) {
+            Ok(m) => m,
+            Err(e) => {
+                println!("{}", e.message);
+                continue;
+            }
+        };
+        if let Some((_, flag)) = STARTUP_FLAGS.iter().find(|(name, _)| matches.is_present(name)) {
+            println!("{} {} only applies when starting didi, it can't be used in the repl", color(Red).paint("Error:"), flag);
+            continue;
+        }
+
+        // a failing command only ends itself
+        let result = match matches.subcommand() {
+            // the following lines are commands, not the content
+            ("add", Some(matches)) => add_entry(&mut diary, matches, config, false),
+            ("list", Some(matches)) => list_entries(&mut diary, matches, config),
+            ("search", Some(matches)) => search_entries(&mut diary, matches, config),
+            _ => Ok(())
+        };
+        if let Err(e) = result {
+            println!("{} {}", color(Red).paint("Error:"), e);
+        }
+
+        // --output only applies to the command it was given to
+        diary.reset_output();
+        set_enabled(colored);
+    }
+}
+
+/// Name of the subcommand given in `matches` including nested ones, e.g. `review list`
+fn command_name(matches: &ArgMatches) -> String {
+    let mut names = Vec::new();
+    let mut matches = matches;
+    while let (name, Some(m)) = matches.subcommand() {
+        names.push(name);
+        matches = m;
+    }
+    names.join(" ")
+}
+
+/// Runs the subcommand given in `matches`
+fn run(matches: &ArgMatches, config: &Config) -> Result<(), DiaryError> {
+    let command = command_name(matches);
+    if matches.is_present("output") && !OUTPUT_COMMANDS.contains(&command.as_str()) {
+        return Err(DiaryError::Invalid(format!("`{}` doesn't support --output", command)));
+    }
+    if matches.is_present("dryrun") && !DRY_RUN_COMMANDS.contains(&command.as_str()) {
+        return Err(DiaryError::Invalid(format!("`{}` doesn't support --dry-run", command)));
+    }
+
+    match matches.subcommand() {
+        ("create", Some(matches)) => {
+            let hash = HashAlgorithm::from_name(matches.value_of("hash").unwrap()).unwrap();
+            let url = Diary::create(hash, |url| {
+                println!("A new {} database will be created at '{}'.", hash.name(),
+                         color(Cyan).paint(url.to_str().unwrap()));
+                if url.exists() {
+                    println!("{}", color(Yellow).paint("Warning: this file already exists!"));
                 }
+                confirm("Create it?", true)
+            })?;
+
+            match url {
+                Some(url) => println!("Created database at '{}'!", color(Cyan).paint(url.as_path().to_str().unwrap())),
+                None => println!("Nothing was created.")
             }
-            let words = raw_content.split_whitespace();
-            let mut content = String::with_capacity(raw_content.len());
-            for word in words {
-                content += word;
-                content += " ";
+        }
+        ("add", Some(matches)) => add_entry(&mut Diary::open()?, matches, config, true)?,
+        ("edit", Some(matches)) => {
+            let mut diary = Diary::open()?;
+            diary.set_max_content_length(config.max_content_length);
+
+            let id = matches.value_of("entry").unwrap().parse().unwrap();
+            let entry = match diary.get_entry(id)? {
+                Some(e) => e,
+                None => {
+                    println!("There is no entry with id {}.", color(Cyan).paint(format!("{}", id)));
+                    return Ok(());
+                }
+            };
+
+            println!("Press ENTER to keep the current value.\n");
+
+            print!("{} [{}]: ", color(Cyan).paint("Title"), entry.title());
+            stdout().flush().unwrap();
+            let raw_title: String = read!("{}\n");
+
+            let use_editor = matches.is_present("editor");
+            let content_file = matches.value_of("contentfile");
+            let raw_content = if let Some(path) = content_file {
+                read_file(path)?
+            } else if use_editor {
+                let edited = read_from_editor(&format!("{}\n", entry.content()))?;
+                if ContentMode::Raw.apply(&edited) == entry.content() { String::new() } else { edited }
+            } else {
+                println!("{}\n{}", color(Cyan).paint("Current content:"), entry.content());
+                print!("{}", color(Cyan).paint("Content: "));
+                stdout().flush().unwrap();
+                let mut first_line = String::new();
+                stdin().read_line(&mut first_line).unwrap();
+                if first_line.trim().is_empty() { String::new() } else { read_content(first_line) }
+            };
+
+            let (raw_title, removed_title) = sanitize_text(&raw_title);
+            let (raw_content, removed_content) = sanitize_text(&raw_content);
+            if removed_title + removed_content > 0 {
+                println!("{} removed {} control characters", color(Yellow).paint("Warning:"),
+                         color(Cyan).paint(format!("{}", removed_title + removed_content)));
             }
 
-            print!("{}", Cyan.paint("Keywords: "));
+            let title = if raw_title.trim().is_empty() { entry.title().to_string() } else { raw_title.trim().into() };
+            let content = if raw_content.is_empty() {
+                entry.content().to_string()
+            } else if content_file.is_some() {
+                raw_content
+            } else if use_editor {
+                ContentMode::Raw.apply(&raw_content)
+            } else {
+                config.content_mode.apply(&raw_content)
+            };
+            diary.check_content_length(&content).map_err(DiaryError::Invalid)?;
+
+            print!("{} [{}]: ", color(Cyan).paint("Keywords"), entry.keywords().join(" "));
             stdout().flush().unwrap();
-            let keywords = {
-                let raw: String = read!("{}\n");
-                raw.split_whitespace().map(|s| s.trim().to_lowercase()).collect()
+            let raw_keywords: String = read!("{}\n");
+            let keywords = if raw_keywords.trim().is_empty() {
+                entry.keywords().to_vec()
+            } else {
+                raw_keywords.split_whitespace().map(|s| s.trim().to_lowercase()).collect::<Vec<String>>()
             };
 
-            diary.add(keywords, title.trim().into(), content);
+            if !accept_keywords(&keywords, "Save anyway?") {
+                println!("Aborted.");
+                return Ok(());
+            }
+
+            diary.update(id, keywords, title.clone(), content)?;
+            diary.attach(id, &attachments(matches))?;
+            if let Some(m) = matches.value_of("mood") {
+                diary.set_mood(id, m.parse().unwrap())?;
+            }
+            println!("Updated {}!", color(Cyan).paint(title));
         }
-        ("list", Some(matches)) => {
-            let mut diary = Diary::open();
+        ("list", Some(matches)) => list_entries(&mut open_listing(matches)?, matches, config)?,
+        ("today", Some(matches)) => {
+            if matches.is_present("add") {
+                return add_entry(&mut Diary::open()?, &default_add_args(), config, true);
+            }
 
-            let date = !matches.is_present("nodate");
-            let id = matches.is_present("id");
-            let hash = matches.is_present("hash");
-            let keywords = matches.is_present("keywords");
-            let content = !matches.is_present("nocontent");
-            let hidden = matches.is_present("hidden");
+            let mut diary = open_listing(matches)?;
+            set_output(&mut diary, matches)?;
 
-            diary.list_all(date, id, hash, keywords, content, hidden);
+            let today = Local::now().format("%Y-%m-%d").to_string();
+            let options = DisplayOptions {
+                from: Some(parse_day(&today, false).unwrap()),
+                to: Some(parse_day(&today, true).unwrap()),
+                ..display_options(matches, config)
+            };
+            diary.list_all(&options, 0, None)?;
         }
-        ("search", Some(matches)) => {
-            let mut diary = Diary::open();
+        ("search", Some(matches)) => search_entries(&mut open_listing(matches)?, matches, config)?,
+        ("hide", Some(matches)) => {
+            let mut diary = Diary::open_combined(databases(matches), true)?;
 
-            let keywords: Vec<String> = matches.values_of("searchfor").unwrap()
-                .map(|s| s.to_lowercase()).collect();
+            let ids = ids_to_update(&mut diary, matches)?;
 
-            let date = !matches.is_present("nodate");
-            let id = matches.is_present("id");
-            let hash = matches.is_present("hash");
-            let showkeywords = matches.is_present("keywords");
-            let content = !matches.is_present("nocontent");
-            let hidden = matches.is_present("hidden");
+            diary.hide(ids, true, matches.is_present("dryrun"), &display_options(matches, config))?;
+        }
+        ("unhide", Some(matches)) => {
+            let mut diary = Diary::open_combined(databases(matches), true)?;
+
+            let ids = ids_to_update(&mut diary, matches)?;
 
-            diary.search(keywords, date, id, hash, showkeywords, content, hidden);
+            diary.hide(ids, false, matches.is_present("dryrun"), &display_options(matches, config))?;
         }
-        ("hide", Some(matches)) => {
-            let mut diary = Diary::open();
+        ("delete", Some(matches)) => {
+            let mut diary = Diary::open_combined(databases(matches), true)?;
 
-            let mut ids: Vec<i64> = matches.values_of("tohide").unwrap()
-                .map(|s| s.parse().unwrap()).collect();
+            let mut ids: Vec<EntryId> = matches.values_of("todelete").unwrap()
+                .map(|s| EntryId::parse(s).unwrap()).collect();
             ids.sort();
             ids.dedup();
 
-            diary.hide(ids, true);
+            let question = if ids.len() == 1 {
+                String::from("Delete 1 entry?")
+            } else {
+                format!("Delete {} entries?", ids.len())
+            };
+            let dry_run = matches.is_present("dryrun");
+            if !dry_run && !matches.is_present("force") && !confirm(&question, false) {
+                println!("Aborted.");
+                return Ok(());
+            }
+
+            let counter = diary.delete(ids, dry_run, &display_options(matches, config))?;
+            if dry_run {
+                println!("Would delete {} {}.", color(Cyan).paint(format!("{}", counter)),
+                         if counter == 1 { "entry" } else { "entries" });
+            } else if counter == 1 {
+                println!("Deleted {} entry.", color(Cyan).paint(format!("{}", counter)));
+            } else {
+                println!("Deleted {} entries.", color(Cyan).paint(format!("{}", counter)));
+            }
         }
-        ("unhide", Some(matches)) => {
-            let mut diary = Diary::open();
+        ("tag", Some(matches)) => {
+            let mut diary = Diary::open()?;
 
-            let mut ids: Vec<i64> = matches.values_of("tohide").unwrap()
-                .map(|s| s.parse().unwrap()).collect();
-            ids.sort();
-            ids.dedup();
+            let id = matches.value_of("entry").unwrap().parse().unwrap();
+            let values = |name| matches.values_of(name)
+                .map(|v| v.map(String::from).collect()).unwrap_or_default();
+            let keywords = diary.retag(id, values("add"), values("remove"))?;
+
+            if keywords.is_empty() {
+                println!("The entry has no keywords.");
+            } else {
+                println!("Keywords: {}", color(Cyan).paint(keywords.join(" ")));
+            }
+        }
+        ("pin", Some(matches)) => {
+            let mut diary = Diary::open()?;
+
+            let ids = matches.values_of("ids").unwrap().map(|s| s.parse().unwrap()).collect();
+            diary.set_pinned(ids, true)?;
+        }
+        ("unpin", Some(matches)) => {
+            let mut diary = Diary::open()?;
 
-            diary.hide(ids, false);
+            let ids = matches.values_of("ids").unwrap().map(|s| s.parse().unwrap()).collect();
+            diary.set_pinned(ids, false)?;
+        }
+        ("repl", Some(_)) => repl(config)?,
+        ("undo", Some(_)) => {
+            let mut diary = Diary::open()?;
+
+            match diary.undo()? {
+                Some((action, 1)) => println!("Undid {} of 1 entry.", action),
+                Some((action, n)) => println!("Undid {} of {} entries.", action, color(Cyan).paint(format!("{}", n))),
+                None => println!("Nothing to undo.")
+            }
+        }
+        ("review", Some(matches)) => {
+            let mut diary = Diary::open()?;
+
+            match matches.subcommand() {
+                ("add", Some(matches)) => {
+                    let ids = matches.values_of("ids").unwrap().map(|s| s.parse().unwrap()).collect();
+                    diary.review_add(ids)?;
+                }
+                ("list", Some(matches)) => {
+                    set_output(&mut diary, matches)?;
+                    diary.review_list(&display_options(matches, config))?;
+                }
+                ("done", Some(matches)) => {
+                    let ids = matches.values_of("ids").unwrap().map(|s| s.parse().unwrap()).collect();
+                    diary.review_done(ids)?;
+                }
+                _ => println!("No review subcommand given. Use flag --help for more information.")
+            }
+        }
+        ("template", Some(matches)) => match matches.subcommand() {
+            ("list", Some(_)) => {
+                let names = templates::list().map_err(DiaryError::Invalid)?;
+                for name in &names {
+                    println!("{}", name);
+                }
+                if names.is_empty() {
+                    println!("No templates found, use `didi template add <name>`.");
+                }
+            }
+            ("show", Some(matches)) => {
+                print!("{}", templates::load(matches.value_of("name").unwrap()).map_err(DiaryError::Invalid)?);
+            }
+            ("add", Some(matches)) => {
+                let name = matches.value_of("name").unwrap();
+                let content = match matches.value_of("file") {
+                    Some(path) => read_file(path)?,
+                    None => read_from_editor(&templates::load(name).unwrap_or_default())?
+                };
+                templates::save(name, &content).map_err(DiaryError::Invalid)?;
+                println!("Saved template {}!", color(Cyan).paint(name));
+            }
+            ("remove", Some(matches)) => {
+                let name = matches.value_of("name").unwrap();
+                templates::remove(name).map_err(DiaryError::Invalid)?;
+                println!("Removed template {}!", color(Cyan).paint(name));
+            }
+            _ => println!("No template subcommand given. Use flag --help for more information.")
+        },
+        ("rebuild-keywords", Some(_)) => {
+            let mut diary = Diary::open()?;
+
+            diary.rebuild_keywords(|_| confirm("Use these keywords?", true))?;
+        }
+        ("keywords", Some(matches)) => {
+            let mut diary = Diary::open_quietly()?;
+
+            let mut histogram = diary.keyword_histogram()?;
+            if matches.is_present("alpha") {
+                histogram.sort_by(|a, b| a.0.cmp(&b.0));
+            }
+
+            let width = histogram.iter().map(|(k, _)| k.chars().count()).max().unwrap_or(0);
+            for (k, n) in &histogram {
+                println!("{:<width$} {}", k, color(Cyan).paint(format!("{}", n)), width = width);
+            }
+        }
+        ("find-orphan-keywords", Some(_)) => {
+            let mut diary = Diary::open()?;
+            diary.orphan_keywords()?;
+        }
+        ("stats", Some(matches)) => {
+            let mut diary = Diary::open_combined(databases(matches), false)?;
+
+            if matches.is_present("calendar") {
+                let year = matches.value_of("calendar").map_or(Local::now().year(), |y| y.parse().unwrap());
+                diary.calendar(year)?;
+            } else {
+                diary.stats()?;
+            }
+        }
+        ("count", Some(matches)) => {
+            let mut diary = Diary::open_quietly()?;
+            let hidden = matches.is_present("hidden");
+
+            match matches.values_of("searchfor") {
+                Some(v) => {
+                    let keywords: Vec<String> = v.flat_map(|s| s.split_whitespace())
+                        .map(|s| s.to_lowercase()).collect();
+                    println!("{}", diary.count_matching(&keywords, hidden)?);
+                }
+                None => println!("{}", diary.count(hidden)?)
+            }
+        }
+        ("completions", Some(matches)) => {
+            let shell: Shell = matches.value_of("shell").unwrap().parse().unwrap();
+            build_app().gen_completions_to("didi", shell, &mut stdout());
+        }
+        ("diaries", Some(_)) => {
+            let diaries = Diary::diaries()?;
+
+            for (name, path) in &diaries {
+                let name = name.as_deref().unwrap_or("(default)");
+                println!("{} {}", color(Cyan).paint(format!("{:<20}", name)), path.display());
+            }
+
+            if diaries.is_empty() {
+                println!("No diaries found, use `didi create` or `didi --diary <name> create`.");
+            }
+        }
+        ("path", Some(_)) => println!("{}", Diary::path(false)?.display()),
+        ("open", Some(_)) => open_with_default_app(&Diary::path(true)?)?,
+        ("verify", Some(matches)) => {
+            let mut diary = Diary::open()?;
+
+            if matches.is_present("interactive") {
+                let counter = diary.verify_interactive(|_| confirm("Store the recomputed hash?", false))?;
+
+                if counter == 1 {
+                    println!("Repaired {} entry.", color(Cyan).paint(format!("{}", counter)));
+                } else {
+                    println!("Repaired {} entries.", color(Cyan).paint(format!("{}", counter)));
+                }
+            } else {
+                let failed = diary.verify()?;
+
+                for id in &failed {
+                    println!("Hash mismatch for entry {}", color(Red).paint(format!("[{}]", id)));
+                }
+
+                if failed.is_empty() {
+                    println!("All entries are intact.");
+                } else if failed.len() == 1 {
+                    println!("Found {} mismatch.", color(Cyan).paint(format!("{}", failed.len())));
+                } else {
+                    println!("Found {} mismatches.", color(Cyan).paint(format!("{}", failed.len())));
+                }
+            }
+        }
+        ("random", Some(matches)) => {
+            let mut diary = Diary::open()?;
+            set_output(&mut diary, matches)?;
+
+            let n = matches.value_of("n").unwrap().parse().unwrap();
+            diary.random(n, &display_options(matches, config))?;
+        }
+        ("show", Some(matches)) => {
+            let mut diary = Diary::open_combined(databases(matches), false)?;
+            set_output(&mut diary, matches)?;
+
+            let ids: Vec<EntryId> = matches.values_of("entry").unwrap().map(|i| EntryId::parse(i).unwrap()).collect();
+            let related = match matches.value_of("related") {
+                Some(n) => Some(n.parse().unwrap()),
+                None if matches.is_present("related") => Some(5),
+                None => None
+            };
+            if related.is_some() && ids.len() > 1 {
+                return Err(DiaryError::Invalid(String::from("--related only works with a single entry")));
+            }
+
+            diary.show(&ids, &display_options(matches, config), related)?;
+        }
+        ("describe", Some(matches)) => {
+            let mut diary = Diary::open_combined(databases(matches), false)?;
+            set_output(&mut diary, matches)?;
+
+            let id = EntryId::parse(matches.value_of("id").unwrap()).unwrap();
+
+            diary.describe(&id)?;
+        }
+        ("import", Some(matches)) => {
+            let mut diary = Diary::open()?;
+            diary.set_max_content_length(config.max_content_length);
+
+            let path = matches.value_of("file").unwrap();
+            let file = File::open(path)
+                .map_err(|e| DiaryError::Invalid(format!("couldn't open file '{}': {}", path, e)))?;
+
+            let check = matches.is_present("check") || matches.is_present("dryrun");
+            let summary = diary.import_json(BufReader::new(file), check)?;
+
+            let count = |n: usize, one: &str, many: &str| {
+                format!("{} {}", color(Cyan).paint(format!("{}", n)), if n == 1 { one } else { many })
+            };
+            let imported = count(summary.imported, "entry", "entries");
+            let skipped = count(summary.skipped, "duplicate", "duplicates");
+            let rejected = count(summary.rejected, "record", "records");
+
+            if check {
+                println!("Would import {}, skip {} and reject {}.", imported, skipped, rejected);
+            } else if summary.skipped + summary.rejected == 0 {
+                println!("Imported {}.", imported);
+            } else {
+                println!("Imported {}, skipped {} and rejected {}.", imported, skipped, rejected);
+            }
+        }
+        ("export", Some(matches)) => {
+            // the export is the only output when it goes to stdout
+            let to_stdout = !matches.is_present("dir") && !matches.is_present("output");
+            let mut diary = if to_stdout { Diary::open_quietly()? } else { Diary::open()? };
+
+            let hidden = matches.is_present("hidden");
+            let after_id = matches.value_of("afterid").map_or(0, |a| a.parse().unwrap());
+            let ids: Vec<i64> = matches.values_of("ids")
+                .map(|v| v.map(|s| s.parse().unwrap()).collect()).unwrap_or_default();
+
+            let (path, counter) = if let Some(dir) = matches.value_of("dir") {
+                let split_by = match matches.value_of("splitby") {
+                    Some("year") => Some(SplitBy::Year),
+                    Some("month") => Some(SplitBy::Month),
+                    _ => None
+                };
+
+                let counter = match export_format(matches, config) {
+                    ExportFormat::Txt => diary.export_txt_dir(Path::new(dir), split_by, hidden, after_id, &ids)?,
+                    _ => return Err(DiaryError::Invalid(String::from("--dir only supports the txt format")))
+                };
+                (dir, counter)
+            } else {
+                let (path, file): (&str, Box<dyn Write>) = match matches.value_of("output") {
+                    Some(path) => (path, Box::new(BufWriter::new(create_file(path)?))),
+                    None => ("stdout", Box::new(stdout()))
+                };
+
+                let counter = match export_format(matches, config) {
+                    ExportFormat::Txt => diary.export_txt(file, hidden, after_id, &ids)?,
+                    ExportFormat::Json => diary.export_json(file, hidden, after_id, &ids, matches.is_present("pretty"))?,
+                    ExportFormat::Markdown => diary.export_markdown(file, hidden, after_id, &ids)?,
+                    ExportFormat::Csv => diary.export_csv(file, hidden, after_id, &ids)?,
+                };
+                (path, counter)
+            };
+
+            if to_stdout {
+                return Ok(());
+            }
+
+            if counter == 1 {
+                println!("Exported {} entry to '{}'!", color(Cyan).paint(format!("{}", counter)), color(Cyan).paint(path));
+            } else {
+                println!("Exported {} entries to '{}'!", color(Cyan).paint(format!("{}", counter)), color(Cyan).paint(path));
+            }
         }
         ("", _) => {
             println!("No subcommand given. Use flag --help for more information.");
@@ -176,5 +1778,42 @@ fn main() {
         _ => unreachable!()
     }
 
-    stdout().flush().unwrap();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_keeps_blank_line_between_paragraphs() {
+        let mut diary = Diary::open_in_memory();
+        let matches = build_app().get_matches_from(
+            ["didi", "add", "--title", "Trip", "--keywords", "travel", "--content", "First day.\n\nSecond day.\n"]);
+
+        add_entry(&mut diary, matches.subcommand_matches("add").unwrap(), &Config::default(), false).unwrap();
+
+        assert_eq!(diary.get_entry(1).unwrap().unwrap().content(), "First day.\n\nSecond day.");
+    }
+
+    #[test]
+    fn today_add_ignores_the_display_flags_of_today() {
+        // `-k` of `today` shows keywords, passed to `add` it would skip the keywords prompt
+        let matches = build_app().get_matches_from(["didi", "today", "--add", "-k"]);
+        assert!(matches.subcommand_matches("today").unwrap().is_present("keywords"));
+
+        let add = default_add_args();
+        assert!(!add.is_present("keywords") && !add.is_present("title") && !add.is_present("editor"));
+    }
+
+    #[test]
+    fn add_rejects_blank_title_before_reading_content() {
+        let mut diary = Diary::open_in_memory();
+        // without --content the content would be read from stdin if the title wasn't checked first
+        let matches = build_app().get_matches_from(["didi", "add", "--title", "   ", "--keywords", "travel"]);
+
+        let result = add_entry(&mut diary, matches.subcommand_matches("add").unwrap(), &Config::default(), false);
+        assert!(matches!(result, Err(DiaryError::Invalid(_))));
+        assert!(diary.get_entry(1).unwrap().is_none());
+    }
 }
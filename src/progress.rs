@@ -0,0 +1,53 @@
+use std::io::{stderr, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Enables or disables progress bars for the whole program
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// A progress bar on stderr for operations over many entries, it is only
+/// shown if stderr is a terminal and progress bars are enabled
+pub struct Progress {
+    label: &'static str,
+    total: usize,
+    current: usize,
+    visible: bool,
+}
+
+impl Progress {
+    pub fn new(label: &'static str, total: usize) -> Self {
+        let visible = ENABLED.load(Ordering::Relaxed) && stderr().is_terminal();
+        let progress = Progress { label, total, current: 0, visible };
+        progress.draw();
+        progress
+    }
+
+    /// Advances the progress by one entry
+    pub fn inc(&mut self) {
+        self.current += 1;
+        self.draw();
+    }
+
+    fn draw(&self) {
+        if !self.visible {
+            return;
+        }
+
+        let width = 30;
+        let filled = (self.current * width).checked_div(self.total).unwrap_or(width);
+        eprint!("\r{} [{}{}] {}/{}", self.label, "#".repeat(filled), " ".repeat(width - filled),
+                self.current, self.total);
+        stderr().flush().unwrap();
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if self.visible {
+            eprintln!();
+        }
+    }
+}
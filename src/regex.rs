@@ -0,0 +1,458 @@
+/// A small regular expression matcher for searching the content of entries.
+///
+/// Supports literals, `.`, classes like `[a-z]` and `[^0-9]`, the escapes `\d`, `\w`, `\s`
+/// (and their negations `\D`, `\W`, `\S`), `\b`, groups `(...)` and `(?:...)`, alternation
+/// `|`, the quantifiers `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}` as well as the anchors `^`
+/// and `$` which match at the start and end of every line. Matching ignores case.
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Vec<ClassItem>, bool),
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    Group(Vec<Vec<Node>>),
+    Repeat(Box<Node>, usize, Option<usize>),
+}
+
+#[derive(Clone, Debug)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Range(a, b) => (a..=b).contains(&c),
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => is_word(c) != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// How deeply groups may be nested, parsing and compiling recurse once per group
+const MAX_DEPTH: usize = 100;
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    /// Number of groups the parser is currently inside of
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn alternatives(&mut self, nested: bool) -> Result<Vec<Vec<Node>>, String> {
+        let mut alternatives = vec![Vec::new()];
+
+        loop {
+            match self.chars.next() {
+                None if nested => return Err(String::from("missing ')'")),
+                None => return Ok(alternatives),
+                Some(')') if nested => return Ok(alternatives),
+                Some(')') => return Err(String::from("unmatched ')'")),
+                Some('|') => alternatives.push(Vec::new()),
+                Some(c) => {
+                    let atom = self.atom(c)?;
+                    let node = self.quantifier(atom)?;
+                    alternatives.last_mut().unwrap().push(node);
+                }
+            }
+        }
+    }
+
+    fn atom(&mut self, c: char) -> Result<Node, String> {
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::LineStart,
+            '$' => Node::LineEnd,
+            '(' => {
+                if self.chars.peek() == Some(&'?') {
+                    self.chars.next();
+                    if self.chars.next() != Some(':') {
+                        return Err(String::from("only non-capturing groups '(?:...)' are supported"));
+                    }
+                }
+                if self.depth == MAX_DEPTH {
+                    return Err(format!("groups can't be nested more than {} levels deep", MAX_DEPTH));
+                }
+                self.depth += 1;
+                let alternatives = self.alternatives(true)?;
+                self.depth -= 1;
+                Node::Group(alternatives)
+            }
+            '[' => self.class()?,
+            '\\' => match self.escape()? {
+                Ok(item) => Node::Class(vec![item], false),
+                Err('b') => Node::WordBoundary,
+                Err(c) => Node::Char(lowercase(c)),
+            },
+            '*' | '+' | '?' | '{' => return Err(format!("'{}' has nothing to repeat", c)),
+            c => Node::Char(lowercase(c)),
+        })
+    }
+
+    /// Reads the character after a `\`, returns the class it stands for or the character itself
+    fn escape(&mut self) -> Result<Result<ClassItem, char>, String> {
+        Ok(match self.chars.next() {
+            Some('d') => Ok(ClassItem::Digit(false)),
+            Some('D') => Ok(ClassItem::Digit(true)),
+            Some('w') => Ok(ClassItem::Word(false)),
+            Some('W') => Ok(ClassItem::Word(true)),
+            Some('s') => Ok(ClassItem::Space(false)),
+            Some('S') => Ok(ClassItem::Space(true)),
+            Some('n') => Err('\n'),
+            Some('t') => Err('\t'),
+            Some(c) if c.is_alphanumeric() && c != 'b' => return Err(format!("unknown escape '\\{}'", c)),
+            Some(c) => Err(c),
+            None => return Err(String::from("pattern ends with '\\'")),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.chars.peek() == Some(&'^');
+        if negated {
+            self.chars.next();
+        }
+
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let start = match self.chars.next() {
+                None => return Err(String::from("missing ']'")),
+                Some(']') if !first => return Ok(Node::Class(items, negated)),
+                Some('\\') => match self.escape()? {
+                    Ok(item) => {
+                        items.push(item);
+                        first = false;
+                        continue;
+                    }
+                    Err(c) => c,
+                },
+                Some(c) => c,
+            };
+            first = false;
+
+            let mut lookahead = self.chars.clone();
+            let end = match (lookahead.next(), lookahead.next()) {
+                (Some('-'), Some(e)) if e != ']' => {
+                    self.chars.next();
+                    self.chars.next();
+                    e
+                }
+                _ => start,
+            };
+            if end < start {
+                return Err(format!("invalid range '{}-{}'", start, end));
+            }
+            items.push(ClassItem::Range(lowercase(start), lowercase(end)));
+            // ranges of uppercase letters have to match the lowercased text as well
+            if start.is_uppercase() || end.is_uppercase() {
+                items.push(ClassItem::Range(start, end));
+            }
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let mut digits = String::new();
+        while let Some(c) = self.chars.peek().filter(|c| c.is_ascii_digit()) {
+            digits.push(*c);
+            self.chars.next();
+        }
+        digits.parse().ok()
+    }
+
+    fn quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let (min, max) = match self.chars.peek() {
+            Some('*' | '+' | '?') => match self.chars.next() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                _ => (0, Some(1)),
+            },
+            Some('{') => {
+                self.chars.next();
+                let min = self.number().ok_or_else(|| String::from("'{' has to be followed by a number"))?;
+                let max = match self.chars.next() {
+                    Some('}') => Some(min),
+                    Some(',') => {
+                        let max = self.number();
+                        if self.chars.next() != Some('}') {
+                            return Err(String::from("missing '}'"));
+                        }
+                        max
+                    }
+                    _ => return Err(String::from("missing '}'")),
+                };
+                if max.is_some_and(|m| m < min) {
+                    return Err(format!("invalid repetition {{{},{}}}", min, max.unwrap()));
+                }
+                (min, max)
+            }
+            _ => return Ok(atom),
+        };
+
+        if matches!(atom, Node::LineStart | Node::LineEnd | Node::WordBoundary) {
+            return Err(String::from("anchors can't be repeated"));
+        }
+        Ok(Node::Repeat(Box::new(atom), min, max))
+    }
+}
+
+/// Instructions of the compiled pattern, they are run by simulating all alternatives at once
+/// so the time is linear in the length of the text and nothing recurses for every character
+#[derive(Clone, Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Vec<ClassItem>, bool),
+    LineStart,
+    LineEnd,
+    WordBoundary,
+    /// Continues at both targets
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// Most instructions a pattern may compile to, counted repetitions copy their pattern
+const MAX_PROGRAM: usize = 10_000;
+
+fn compile_alternatives(alternatives: Vec<Vec<Node>>, program: &mut Vec<Inst>) -> Result<(), String> {
+    let mut jumps = Vec::new();
+    let count = alternatives.len();
+
+    for (i, alternative) in alternatives.into_iter().enumerate() {
+        let split = program.len();
+        if i + 1 < count {
+            program.push(Inst::Split(split + 1, 0));
+        }
+        for node in alternative {
+            compile(node, program)?;
+        }
+        if i + 1 < count {
+            jumps.push(program.len());
+            program.push(Inst::Jump(0));
+            program[split] = Inst::Split(split + 1, program.len());
+        }
+    }
+
+    let end = program.len();
+    for j in jumps {
+        program[j] = Inst::Jump(end);
+    }
+    Ok(())
+}
+
+fn compile(node: Node, program: &mut Vec<Inst>) -> Result<(), String> {
+    if program.len() > MAX_PROGRAM {
+        return Err(String::from("pattern is too large"));
+    }
+
+    match node {
+        Node::Char(c) => program.push(Inst::Char(c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(items, negated) => program.push(Inst::Class(items, negated)),
+        Node::LineStart => program.push(Inst::LineStart),
+        Node::LineEnd => program.push(Inst::LineEnd),
+        Node::WordBoundary => program.push(Inst::WordBoundary),
+        Node::Group(alternatives) => compile_alternatives(alternatives, program)?,
+        Node::Repeat(inner, min, max) => {
+            // the pattern is compiled into a separate program first so it can be copied
+            let mut body = Vec::new();
+            compile(*inner, &mut body)?;
+            let copies = min + max.map_or(1, |m| m - min);
+            if body.len().saturating_mul(copies) > MAX_PROGRAM {
+                return Err(String::from("pattern is too large"));
+            }
+
+            let append = |program: &mut Vec<Inst>| {
+                let offset = program.len();
+                program.extend(body.iter().map(|i| match i {
+                    Inst::Split(a, b) => Inst::Split(a + offset, b + offset),
+                    Inst::Jump(a) => Inst::Jump(a + offset),
+                    i => i.clone(),
+                }));
+            };
+
+            for _ in 0..min {
+                append(program);
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    append(program);
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in min..max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        append(program);
+                    }
+                    let end = program.len();
+                    for s in splits {
+                        program[s] = Inst::Split(s + 1, end);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Regex {
+    /// Compiles `pattern`, the error explains what is wrong with it
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let mut parser = Parser { chars: pattern.chars().peekable(), depth: 0 };
+        let mut program = Vec::new();
+        compile_alternatives(parser.alternatives(false)?, &mut program)?;
+        program.push(Inst::Match);
+        Ok(Regex { program })
+    }
+
+    /// Adds the thread at `pc` and every thread reachable from it without consuming a character
+    /// to `threads`, returns whether one of them matched
+    fn add_thread(&self, threads: &mut Vec<usize>, seen: &mut [usize], generation: usize, pc: usize,
+                  text: &[char], pos: usize) -> bool {
+        let before = pos.checked_sub(1).map(|p| text[p]);
+        let c = text.get(pos).copied();
+
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if seen[pc] == generation {
+                continue;
+            }
+            seen[pc] = generation;
+
+            match self.program[pc] {
+                Inst::Split(a, b) => {
+                    stack.push(b);
+                    stack.push(a);
+                }
+                Inst::Jump(a) => stack.push(a),
+                Inst::LineStart => if before.is_none() || before == Some('\n') { stack.push(pc + 1) },
+                Inst::LineEnd => if c.is_none() || c == Some('\n') { stack.push(pc + 1) },
+                Inst::WordBoundary => if before.is_some_and(is_word) != c.is_some_and(is_word) { stack.push(pc + 1) },
+                Inst::Match => return true,
+                _ => threads.push(pc),
+            }
+        }
+
+        false
+    }
+
+    /// Whether the pattern matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().map(lowercase).collect();
+        let mut seen = vec![usize::MAX; self.program.len()];
+        let mut current = Vec::new();
+        let mut next = Vec::new();
+
+        for pos in 0..=text.len() {
+            // a new thread starts at every position since the match may begin anywhere
+            if self.add_thread(&mut current, &mut seen, pos, 0, &text, pos) {
+                return true;
+            }
+            let c = match text.get(pos) {
+                Some(c) => *c,
+                None => break,
+            };
+            let upper = c.to_uppercase().next().unwrap_or(c);
+
+            for &pc in &current {
+                let matched = match &self.program[pc] {
+                    Inst::Char(expected) => c == *expected,
+                    Inst::Any => c != '\n',
+                    Inst::Class(items, negated) => items.iter().any(|i| i.matches(c) || i.matches(upper)) != *negated,
+                    _ => false,
+                };
+                if matched && self.add_thread(&mut next, &mut seen, pos + 1, pc + 1, &text, pos + 1) {
+                    return true;
+                }
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn classes() {
+        assert!(is_match("[a-c]x", "bx"));
+        assert!(!is_match("[a-c]x", "dx"));
+        assert!(is_match("[^0-9]", "a"));
+        assert!(!is_match("^[^0-9]+$", "123"));
+        assert!(is_match(r"\d\s\w", "1 a"));
+        assert!(is_match(r"[\d-]+", "2024-01"));
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(is_match("^day$", "day"));
+        assert!(is_match("^day$", "first\nday\nlast"));
+        assert!(!is_match("^day", "today"));
+        assert!(is_match(r"\bday\b", "a day off"));
+        assert!(!is_match(r"\bday\b", "today"));
+    }
+
+    #[test]
+    fn counted_repetition() {
+        assert!(is_match("^a{3}$", "aaa"));
+        assert!(!is_match("^a{3}$", "aa"));
+        assert!(is_match("^a{2,}$", "aaaa"));
+        assert!(is_match("^(?:ab){1,2}$", "abab"));
+        assert!(!is_match("^(?:ab){1,2}$", "ababab"));
+    }
+
+    #[test]
+    fn case_folding() {
+        assert!(is_match("berlin", "Trip to BERLIN"));
+        assert!(is_match("BERLIN", "trip to berlin"));
+        assert!(is_match("[A-C]", "b"));
+        assert!(is_match("caf[É]", "CAFÉ"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in ["(a", "a)", "[a", "*a", "a{2,1}", "a{x}", r"\q", "(?=a)", "^*", "a\\"] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn deep_nesting() {
+        assert!(is_match(&format!("{}a{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH)), "a"));
+        assert!(Regex::new(&"(".repeat(50_000)).is_err());
+        assert!(Regex::new(&format!("{}a{}", "(".repeat(MAX_DEPTH + 1), ")".repeat(MAX_DEPTH + 1))).is_err());
+    }
+}
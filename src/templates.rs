@@ -0,0 +1,85 @@
+use std::fs::{create_dir_all, read_dir, read_to_string, remove_file, write};
+use std::path::PathBuf;
+
+use chrono::Local;
+use dirs::config_dir;
+
+/// Directory the templates are stored in, uses the environment variable `DIDI_TEMPLATES` if
+/// specified otherwise `didi/templates` in the users config directory.
+fn dir() -> Result<PathBuf, String> {
+    if let Ok(d) = std::env::var("DIDI_TEMPLATES") {
+        return Ok(PathBuf::from(d));
+    }
+
+    config_dir()
+        .map(|mut d| {
+            d.push("didi");
+            d.push("templates");
+            d
+        })
+        .ok_or_else(|| String::from("couldn't retrieve config directory"))
+}
+
+/// Template names are used as file names, so only letters, digits, '-' and '_' are allowed
+pub fn validate_name(name: &str) -> Result<(), String> {
+    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        Ok(())
+    } else {
+        Err(String::from("template names may only contain letters, digits, '-' and '_'"))
+    }
+}
+
+fn path(name: &str) -> Result<PathBuf, String> {
+    validate_name(name)?;
+    let mut path = dir()?;
+    path.push(format!("{}.txt", name));
+    Ok(path)
+}
+
+/// Names of all stored templates in alphabetical order
+pub fn list() -> Result<Vec<String>, String> {
+    let dir = dir()?;
+    let files = match read_dir(&dir) {
+        Ok(f) => f,
+        Err(_) => return Ok(Vec::new())
+    };
+
+    let mut names: Vec<String> = files
+        .filter_map(|f| f.ok())
+        .filter_map(|f| {
+            let name = f.file_name().to_string_lossy().strip_suffix(".txt")?.to_string();
+            validate_name(&name).ok().map(|_| name)
+        })
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Reads the template `name` as it is stored
+pub fn load(name: &str) -> Result<String, String> {
+    let path = path(name)?;
+    read_to_string(&path).map_err(|_| format!("there is no template named '{}'", name))
+}
+
+/// Stores `content` as the template `name`, an existing template is replaced
+pub fn save(name: &str, content: &str) -> Result<(), String> {
+    let path = path(name)?;
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).map_err(|e| format!("couldn't create directory '{}': {}", parent.display(), e))?;
+    }
+    write(&path, content).map_err(|e| format!("couldn't write template '{}': {}", path.display(), e))
+}
+
+/// Deletes the template `name`
+pub fn remove(name: &str) -> Result<(), String> {
+    let path = path(name)?;
+    remove_file(&path).map_err(|_| format!("there is no template named '{}'", name))
+}
+
+/// Fills in the placeholders `{date}` (`YYYY-MM-DD`) and `{weekday}` (e.g. `Monday`) with the current day
+pub fn render(template: &str) -> String {
+    let now = Local::now();
+    template
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{weekday}", &now.format("%A").to_string())
+}